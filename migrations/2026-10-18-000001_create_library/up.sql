@@ -0,0 +1,8 @@
+CREATE TABLE library (
+    path TEXT PRIMARY KEY NOT NULL,
+    title TEXT NOT NULL,
+    artist TEXT,
+    album TEXT,
+    duration INTEGER,
+    modified BIGINT NOT NULL
+);
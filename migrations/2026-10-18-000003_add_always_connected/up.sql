@@ -0,0 +1 @@
+ALTER TABLE guild_settings ADD COLUMN always_connected BOOLEAN NOT NULL DEFAULT 0;
@@ -0,0 +1,2 @@
+DROP TABLE queued_tracks;
+DROP TABLE saved_queues;
@@ -0,0 +1,22 @@
+CREATE TABLE saved_queues (
+    guild_id BIGINT PRIMARY KEY NOT NULL,
+    voice_channel BIGINT NOT NULL,
+    text_channel BIGINT NOT NULL,
+    loop_mode TEXT NOT NULL,
+    volume INTEGER NOT NULL,
+    position BIGINT NOT NULL
+);
+
+CREATE TABLE queued_tracks (
+    guild_id BIGINT NOT NULL,
+    track_index INTEGER NOT NULL,
+    source TEXT NOT NULL,
+    title TEXT NOT NULL,
+    author TEXT,
+    duration INTEGER,
+    url TEXT,
+    thumbnail TEXT,
+    requester BIGINT,
+    enqueued_at BIGINT NOT NULL,
+    PRIMARY KEY (guild_id, track_index)
+);
@@ -0,0 +1,3 @@
+DROP TABLE loudness;
+
+ALTER TABLE guild_settings DROP COLUMN normalize;
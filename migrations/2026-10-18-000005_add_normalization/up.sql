@@ -0,0 +1,6 @@
+ALTER TABLE guild_settings ADD COLUMN normalize BOOLEAN NOT NULL DEFAULT 0;
+
+CREATE TABLE loudness (
+    source TEXT PRIMARY KEY NOT NULL,
+    integrated DOUBLE NOT NULL
+);
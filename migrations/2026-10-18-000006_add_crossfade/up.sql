@@ -0,0 +1 @@
+ALTER TABLE guild_settings ADD COLUMN crossfade INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,3 @@
+ALTER TABLE guild_settings DROP COLUMN playlist_role;
+DROP TABLE playlist_tracks;
+DROP TABLE playlists;
@@ -0,0 +1,21 @@
+CREATE TABLE playlists (
+    id INTEGER PRIMARY KEY NOT NULL,
+    owner_id BIGINT NOT NULL,
+    guild BOOLEAN NOT NULL,
+    name TEXT NOT NULL,
+    UNIQUE (owner_id, guild, name)
+);
+
+CREATE TABLE playlist_tracks (
+    playlist_id INTEGER NOT NULL,
+    track_index INTEGER NOT NULL,
+    source TEXT NOT NULL,
+    title TEXT NOT NULL,
+    author TEXT,
+    duration INTEGER,
+    url TEXT,
+    thumbnail TEXT,
+    PRIMARY KEY (playlist_id, track_index)
+);
+
+ALTER TABLE guild_settings ADD COLUMN playlist_role BIGINT;
@@ -0,0 +1 @@
+ALTER TABLE guild_settings ADD COLUMN fair_queue BOOLEAN NOT NULL DEFAULT 0;
@@ -0,0 +1,7 @@
+CREATE TABLE recent_plays (
+    user_id BIGINT NOT NULL,
+    query TEXT NOT NULL,
+    title TEXT NOT NULL,
+    played_at BIGINT NOT NULL,
+    PRIMARY KEY (user_id, query)
+);
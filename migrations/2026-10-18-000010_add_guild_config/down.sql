@@ -0,0 +1,6 @@
+ALTER TABLE guild_settings DROP COLUMN music_channel;
+ALTER TABLE guild_settings DROP COLUMN max_track_duration;
+ALTER TABLE guild_settings DROP COLUMN max_queue_length;
+ALTER TABLE guild_settings DROP COLUMN default_volume;
+ALTER TABLE guild_settings DROP COLUMN announce_songs;
+ALTER TABLE guild_settings DROP COLUMN prefix;
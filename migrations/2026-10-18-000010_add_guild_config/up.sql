@@ -0,0 +1,6 @@
+ALTER TABLE guild_settings ADD COLUMN prefix TEXT;
+ALTER TABLE guild_settings ADD COLUMN announce_songs BOOLEAN NOT NULL DEFAULT 1;
+ALTER TABLE guild_settings ADD COLUMN default_volume INTEGER NOT NULL DEFAULT 100;
+ALTER TABLE guild_settings ADD COLUMN max_queue_length INTEGER;
+ALTER TABLE guild_settings ADD COLUMN max_track_duration INTEGER;
+ALTER TABLE guild_settings ADD COLUMN music_channel BIGINT;
@@ -0,0 +1,334 @@
+//! Suggestions for the query of the play slash command and the names of playlists. Discord asks for
+//! suggestions after every key the user presses, so searches wait for the user to stop typing and
+//! their results are cached.
+
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use serenity::{
+    all::{CommandInteraction, GuildId, UserId},
+    builder::{CreateAutocompleteResponse, CreateInteractionResponse},
+    client::Context,
+    prelude::TypeMapKey,
+};
+
+use crate::{
+    guilds::music::track::Track,
+    models::{
+        playlist::{Owner, Playlist},
+        recent::RecentPlay,
+    },
+    DatabaseKey, SourceKey,
+};
+
+use super::playlist::QUERY_PREFIX;
+
+/// How long the user has to stop typing before the query is searched
+const DEBOUNCE: Duration = Duration::from_millis(400);
+/// How long search results are reused for the same query
+const CACHE_DURATION: Duration = Duration::from_secs(120);
+/// How many queries are cached at most, the oldest results are removed first
+const CACHE_SIZE: usize = 200;
+/// Shorter queries aren't searched, their results are rarely what the user is looking for
+const MIN_SEARCH_LENGTH: usize = 3;
+/// How many of the user's recent plays are suggested at most
+const MAX_RECENT_PLAYS: usize = 5;
+/// How many suggestions Discord shows at most
+const MAX_CHOICES: usize = 25;
+/// How many characters the name and value of a suggestion may have
+const MAX_CHOICE_LENGTH: usize = 100;
+
+#[derive(Clone)]
+struct Choice {
+    name: String,
+    /// What is used as the query once the user picks the suggestion
+    value: String,
+}
+
+#[derive(Default)]
+pub struct Autocomplete {
+    /// The latest request of each user that is waiting to search, older requests are dropped
+    latest: HashMap<UserId, u64>,
+    requests: u64,
+    /// Search results by lowercase query, along with when they were searched
+    cache: HashMap<String, (Instant, Vec<Choice>)>,
+}
+
+impl Autocomplete {
+    fn cached(&self, query: &str) -> Option<Vec<Choice>> {
+        self.cache
+            .get(query)
+            .filter(|(searched, _)| searched.elapsed() < CACHE_DURATION)
+            .map(|(_, choices)| choices.clone())
+    }
+
+    /// Starts waiting to search for the user, replacing the request they were waiting on before
+    fn begin(&mut self, user: UserId) -> u64 {
+        self.requests += 1;
+        self.latest.insert(user, self.requests);
+        self.requests
+    }
+
+    fn is_latest(&self, user: UserId, request: u64) -> bool {
+        self.latest.get(&user) == Some(&request)
+    }
+
+    fn finish(&mut self, user: UserId, request: u64) {
+        if self.is_latest(user, request) {
+            self.latest.remove(&user);
+        }
+    }
+
+    fn store(&mut self, query: String, choices: Vec<Choice>) {
+        self.cache
+            .retain(|_, (searched, _)| searched.elapsed() < CACHE_DURATION);
+
+        if self.cache.len() >= CACHE_SIZE {
+            let oldest = self
+                .cache
+                .iter()
+                .min_by_key(|(_, (searched, _))| *searched)
+                .map(|(query, _)| query.clone());
+
+            if let Some(oldest) = oldest {
+                self.cache.remove(&oldest);
+            }
+        }
+
+        self.cache.insert(query, (Instant::now(), choices));
+    }
+}
+
+pub struct AutocompleteKey;
+
+impl TypeMapKey for AutocompleteKey {
+    type Value = Autocomplete;
+}
+
+/// Suggests the user's playlists, the guild's playlists, the user's recent plays and search results
+pub async fn play(ctx: &Context, interaction: &CommandInteraction) -> Result<()> {
+    let query = interaction
+        .data
+        .autocomplete()
+        .map_or("", |option| option.value)
+        .trim();
+    let user = interaction.user.id;
+
+    let mut choices = saved(ctx, user, interaction.guild_id, query).await?;
+    match search(ctx, user, query).await {
+        Ok(Some(results)) => choices.extend(results),
+        // The user kept typing, the newer request answers instead
+        Ok(None) => return Ok(()),
+        Err(why) => println!("Failed to search for suggestions: {:?}", why),
+    }
+
+    respond(ctx, interaction, choices).await
+}
+
+/// Suggests the names of the playlists the subcommand of the playlist command can use
+pub async fn playlist(
+    ctx: &Context,
+    interaction: &CommandInteraction,
+    subcommand: &str,
+    guild: bool,
+) -> Result<()> {
+    let query = interaction
+        .data
+        .autocomplete()
+        .map_or("", |option| option.value)
+        .trim()
+        .to_lowercase();
+
+    // Only playlists are played and shown from either owner, the others change the chosen owner's
+    let owners = match (guild, interaction.guild_id) {
+        (true, Some(guild)) => vec![Owner::Guild(guild)],
+        (false, Some(guild)) if matches!(subcommand, "show" | "play") => {
+            vec![Owner::User(interaction.user.id), Owner::Guild(guild)]
+        }
+        _ => vec![Owner::User(interaction.user.id)],
+    };
+
+    let database = {
+        let typemap = ctx.data.read().await;
+        typemap
+            .get::<DatabaseKey>()
+            .expect("Expected DatabaseKey in TypeMap.")
+            .clone()
+    };
+
+    let mut choices = Vec::new();
+    {
+        let mut connection = database.lock().await;
+        for owner in owners {
+            choices.extend(
+                Playlist::list(&mut connection, owner)?
+                    .into_iter()
+                    .filter(|playlist| playlist.name.to_lowercase().contains(&query))
+                    .map(|playlist| Choice {
+                        name: playlist.name.clone(),
+                        value: playlist.name,
+                    }),
+            );
+        }
+    }
+
+    respond(ctx, interaction, choices).await
+}
+
+/// Sends the suggestions that fit, without repeating the same value
+async fn respond(
+    ctx: &Context,
+    interaction: &CommandInteraction,
+    choices: Vec<Choice>,
+) -> Result<()> {
+    let mut seen = HashSet::new();
+    let response = choices
+        .into_iter()
+        .filter(|choice| choice.value.chars().count() <= MAX_CHOICE_LENGTH)
+        .filter(|choice| seen.insert(choice.value.clone()))
+        .take(MAX_CHOICES)
+        .fold(CreateAutocompleteResponse::new(), |response, choice| {
+            response.add_string_choice(truncate(&choice.name), choice.value)
+        });
+
+    interaction
+        .create_response(&ctx.http, CreateInteractionResponse::Autocomplete(response))
+        .await?;
+
+    Ok(())
+}
+
+/// The playlists and recent plays matching the query, the user's own playlists come first
+async fn saved(
+    ctx: &Context,
+    user: UserId,
+    guild: Option<GuildId>,
+    query: &str,
+) -> Result<Vec<Choice>> {
+    let database = {
+        let typemap = ctx.data.read().await;
+        typemap
+            .get::<DatabaseKey>()
+            .expect("Expected DatabaseKey in TypeMap.")
+            .clone()
+    };
+
+    let query = query.to_lowercase();
+    let matches = |text: &str| text.to_lowercase().contains(&query);
+
+    let mut connection = database.lock().await;
+    let mut playlists = Playlist::list(&mut connection, Owner::User(user))?;
+    if let Some(guild) = guild {
+        playlists.extend(Playlist::list(&mut connection, Owner::Guild(guild))?);
+    }
+
+    let mut choices = playlists
+        .into_iter()
+        .filter(|playlist| matches(&playlist.name))
+        .map(|playlist| Choice {
+            name: format!("Playlist: {}", playlist.name),
+            value: format!("{}{}", QUERY_PREFIX, playlist.name),
+        })
+        .collect::<Vec<_>>();
+
+    choices.extend(
+        RecentPlay::list(&mut connection, user)?
+            .into_iter()
+            .filter(|play| matches(&play.title) || matches(&play.query))
+            .take(MAX_RECENT_PLAYS)
+            .map(|play| Choice {
+                name: format!("Recently played: {}", play.title),
+                value: play.query,
+            }),
+    );
+
+    Ok(choices)
+}
+
+/// Search results for the query once the user stopped typing, `None` if they kept typing
+async fn search(ctx: &Context, user: UserId, query: &str) -> Result<Option<Vec<Choice>>> {
+    if query.chars().count() < MIN_SEARCH_LENGTH || query.starts_with(QUERY_PREFIX) {
+        return Ok(Some(Vec::new()));
+    }
+
+    let key = query.to_lowercase();
+    let request = {
+        let mut typemap = ctx.data.write().await;
+        let autocomplete = typemap
+            .get_mut::<AutocompleteKey>()
+            .expect("Expected AutocompleteKey in TypeMap.");
+
+        if let Some(choices) = autocomplete.cached(&key) {
+            return Ok(Some(choices));
+        }
+
+        autocomplete.begin(user)
+    };
+
+    tokio::time::sleep(DEBOUNCE).await;
+
+    let sources = {
+        let typemap = ctx.data.read().await;
+        let autocomplete = typemap
+            .get::<AutocompleteKey>()
+            .expect("Expected AutocompleteKey in TypeMap.");
+
+        if !autocomplete.is_latest(user, request) {
+            return Ok(None);
+        }
+
+        typemap
+            .get::<SourceKey>()
+            .expect("Expected SourceKey in TypeMap.")
+            .clone()
+    };
+
+    let choices = sources
+        .search(query)
+        .await
+        .map(|tracks| tracks.iter().filter_map(to_choice).collect::<Vec<_>>());
+
+    let mut typemap = ctx.data.write().await;
+    let autocomplete = typemap
+        .get_mut::<AutocompleteKey>()
+        .expect("Expected AutocompleteKey in TypeMap.");
+
+    autocomplete.finish(user, request);
+    if let Ok(choices) = &choices {
+        autocomplete.store(key, choices.clone());
+    }
+
+    Ok(Some(choices?))
+}
+
+/// Search results are played through their link, tracks without one can't be suggested
+fn to_choice(track: &Track) -> Option<Choice> {
+    let name = match &track.author {
+        Some(author) => format!(
+            "{} - {} ({})",
+            track.title,
+            author,
+            track.display_duration()
+        ),
+        None => format!("{} ({})", track.title, track.display_duration()),
+    };
+
+    Some(Choice {
+        name,
+        value: track.url.clone()?,
+    })
+}
+
+/// Shortens the text to fit in a suggestion
+fn truncate(text: &str) -> String {
+    if text.chars().count() <= MAX_CHOICE_LENGTH {
+        return text.to_string();
+    }
+
+    let mut truncated = text.chars().take(MAX_CHOICE_LENGTH - 1).collect::<String>();
+    truncated.push('…');
+    truncated
+}
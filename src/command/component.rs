@@ -0,0 +1,79 @@
+//! Buttons of the player, these run the shared commands for the user that pressed them
+
+use serenity::{all::ComponentInteraction, client::Context, framework::standard::CommandResult};
+
+use crate::{
+    discord::invocation::Invocation,
+    guilds::music::player,
+    helper::{
+        embed,
+        music::{get_bot_channel, get_manager, get_voice_channel},
+    },
+};
+
+use super::music;
+
+/// Runs the button that was pressed. Other components, such as the picker, are left to whoever
+/// is waiting for them.
+pub async fn run(ctx: &Context, interaction: &ComponentInteraction) {
+    if !interaction.data.custom_id.starts_with(player::PREFIX) || interaction.guild_id.is_none() {
+        return;
+    }
+
+    let invocation = Invocation::component(ctx, interaction);
+    if let Err(why) = press(&invocation, &interaction.data.custom_id).await {
+        println!(
+            "Failed to run player button {}: {:?}",
+            interaction.data.custom_id, why
+        );
+
+        let _ = invocation
+            .error(embed::error(
+                "Something went wrong while pressing the button!",
+            ))
+            .await;
+    }
+}
+
+async fn press(invocation: &Invocation<'_>, button: &str) -> CommandResult {
+    let ctx = invocation.ctx;
+    let guild = invocation.guild_id();
+
+    // Only the people listening get to control the music
+    let channel = get_bot_channel(ctx, guild);
+    if channel.is_none() || channel != get_voice_channel(ctx, guild, invocation.author().id) {
+        invocation
+            .error(embed::error(
+                "You need to be in the same voice channel as the bot to use the player!",
+            ))
+            .await?;
+
+        return Ok(());
+    }
+
+    match button {
+        player::PAUSE => music::pause(invocation).await?,
+        player::RESUME => music::resume(invocation).await?,
+        player::SKIP => music::skip(invocation).await?,
+        player::PREVIOUS => music::previous(invocation).await?,
+        player::LOOP => {
+            let mode = {
+                let manager = get_manager(ctx, guild).await;
+                let music = manager.lock().await;
+                music.get_loop().cycle()
+            };
+
+            music::loop_mode(invocation, Some(&mode.to_string())).await?
+        }
+        player::SHUFFLE => music::shuffle(invocation).await?,
+        player::STOP => music::stop(invocation).await?,
+        _ => {
+            invocation
+                .error(embed::error("This button doesn't do anything anymore!"))
+                .await?
+        }
+    }
+
+    invocation.acknowledge().await?;
+    Ok(())
+}
@@ -1,40 +1,38 @@
 use std::time::Duration;
 
 use serenity::{
-    all::Message,
+    all::{ChannelType, Permissions},
     builder::EditMessage,
-    client::Context,
-    framework::standard::{
-        macros::{command, group},
-        Args, CommandResult,
-    },
+    framework::standard::CommandResult,
     model::Timestamp,
 };
 
-use crate::helper::{
-    embed, emoji,
-    helper::{to_ms, SendEmbed},
+use crate::{
+    discord::{
+        invocation::Invocation,
+        roles::{self, RoleExt},
+    },
+    helper::{embed, emoji, helper::to_ms, paginator::Paginator, settings},
+    models::settings::{GuildSettings, Setting},
 };
 
-#[group]
-#[commands(ping)]
-struct General;
+/// How many roles are shown on a single page
+const ROLES_PAGE_SIZE: usize = 20;
 
-#[command]
-async fn ping(ctx: &Context, msg: &Message, mut _args: Args) -> CommandResult {
-    let loading = emoji::get_bot_emote(&ctx, "p_music").await?;
+pub async fn ping(invocation: &Invocation<'_>) -> CommandResult {
+    let ctx = invocation.ctx;
+    let loading = emoji::get_bot_emote(ctx, "p_music").await?;
     let time_started = Timestamp::now();
 
-    let mut message = msg
-        .channel_id
-        .send_embed(
-            &ctx.http,
+    let mut message = invocation
+        .send(
             embed::build(format!(
                 "**Bot Ping**
 				
 				One way ping: {loading}
 				Two way ping: {loading}",
             )),
+            Vec::new(),
         )
         .await?;
 
@@ -59,6 +57,146 @@ async fn ping(ctx: &Context, msg: &Message, mut _args: Args) -> CommandResult {
     Ok(())
 }
 
+/// Lists the roles of the guild, from the highest to the lowest
+pub async fn roles(invocation: &Invocation<'_>) -> CommandResult {
+    let roles = roles::get_guild_roles(invocation.ctx, invocation.guild_id()).await;
+
+    let lines = roles
+        .iter()
+        .enumerate()
+        .map(|(i, role)| format!("`{}.` {} `{}`", i + 1, role.as_mention(), role.id));
+
+    Paginator::new(
+        format!("**Roles** ({})\n", roles.len()),
+        lines,
+        ROLES_PAGE_SIZE,
+    )
+    .show(invocation, 0)
+    .await?;
+
+    Ok(())
+}
+
+/// Shows the settings of the guild, or changes the setting when a value is given
+pub async fn config(
+    invocation: &Invocation<'_>,
+    setting: Option<&str>,
+    value: Option<&str>,
+) -> CommandResult {
+    let ctx = invocation.ctx;
+    let mut settings = settings::get_settings(ctx, invocation.guild_id()).await?;
+
+    let Some(name) = setting else {
+        let lines = Setting::ALL
+            .iter()
+            .map(|setting| {
+                format!(
+                    "`{}` {}\n{}",
+                    setting,
+                    settings.display(*setting),
+                    setting.description()
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n");
+
+        invocation
+            .reply(embed::build(format!(
+                "**Settings**\n\n{}\n\nUsage: `config <setting> [value]`, use `reset` as the value to go back to the default",
+                lines
+            )))
+            .await?;
+
+        return Ok(());
+    };
+
+    let Ok(setting) = name.parse::<Setting>() else {
+        let names = Setting::ALL
+            .iter()
+            .map(|setting| format!("`{}`", setting))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        invocation
+            .error(embed::error(format!(
+                "There is no setting called `{}`, use one of {}",
+                name, names
+            )))
+            .await?;
+
+        return Ok(());
+    };
+
+    let Some(value) = value else {
+        invocation
+            .reply(embed::build(format!(
+                "`{}` is set to {}\n{}\nUsage: `config {} <value|reset>`",
+                setting,
+                settings.display(setting),
+                setting.description(),
+                setting
+            )))
+            .await?;
+
+        return Ok(());
+    };
+
+    let member = invocation.member().await?;
+    if !roles::has_permission(ctx, &member, Permissions::MANAGE_GUILD).await {
+        invocation
+            .error(embed::error(
+                "You need the Manage Server permission to change the settings!",
+            ))
+            .await?;
+
+        return Ok(());
+    }
+
+    if let Err(e) = settings.set(setting, value) {
+        invocation.error(embed::error(format!("{}!", e))).await?;
+        return Ok(());
+    }
+
+    if let Some(error) = check_exists(invocation, &settings, setting) {
+        invocation.error(embed::error(error)).await?;
+        return Ok(());
+    }
+
+    settings::save_settings(ctx, &settings).await?;
+
+    let mut reply = format!("Set `{}` to {}", setting, settings.display(setting));
+    if matches!(setting, Setting::DefaultVolume | Setting::MusicChannel) {
+        reply.push_str("\nThis is used from the next time I join a voice channel");
+    }
+
+    invocation.reply(embed::build(reply)).await?;
+    Ok(())
+}
+
+/// Makes sure the role or channel the setting was changed to is part of the guild
+fn check_exists(
+    invocation: &Invocation<'_>,
+    settings: &GuildSettings,
+    setting: Setting,
+) -> Option<&'static str> {
+    let guild = invocation.ctx.cache.guild(invocation.guild_id())?;
+    match setting {
+        Setting::DjRole => settings
+            .dj_role()
+            .filter(|role| !guild.roles.contains_key(role))
+            .map(|_| "That role isn't part of this server!"),
+        Setting::MusicChannel => settings
+            .music_channel()
+            .filter(|channel| {
+                !guild.channels.get(channel).is_some_and(|channel| {
+                    matches!(channel.kind, ChannelType::Text | ChannelType::News)
+                })
+            })
+            .map(|_| "The music channel must be a text channel of this server!"),
+        _ => None,
+    }
+}
+
 fn get_duration(left: Timestamp, right: Timestamp) -> Duration {
     Duration::from_millis(left.millisecond() as u64 - right.millisecond() as u64)
 }
@@ -1,3 +1,8 @@
+pub mod autocomplete;
+pub mod component;
 pub mod general;
 pub mod music;
+pub mod playlist;
+pub mod prefix;
+pub mod slash;
 // pub mod handler;
@@ -1,136 +1,1113 @@
-use std::sync::Arc;
+//! The music commands, shared by prefix and slash commands
 
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
 use serenity::{
-    all::Message,
-    client::Context,
-    framework::standard::{
-        macros::{command, group},
-        Args, CommandResult,
-    },
+    all::{Permissions, RoleId},
+    builder::EditMessage,
+    framework::standard::{Args, CommandResult},
+    prelude::Mentionable,
 };
-use songbird::SongbirdKey;
 
 use crate::{
-    guilds::{
-        data::GuildContext,
-        music::{handler::MusicHandler, track::Track},
+    discord::{invocation::Invocation, roles},
+    guilds::music::{
+        filter::{self, Filters, Style},
+        manager::{Loop, MusicManager},
+        player,
+        source::Resolved,
+        track::Track,
     },
-    helper::{embed, emoji, helper::SendEmbed, music},
-    HttpKey,
+    helper::{
+        embed, emoji,
+        helper::{format_duration, parse_timestamp},
+        music,
+        paginator::Paginator,
+        picker, settings,
+    },
+    models::recent::RecentPlay,
+    ConfigKey, DatabaseKey, SourceKey,
 };
 
-#[group]
-#[commands(play, countdown, skip)]
-struct Music;
-
-#[command]
-async fn countdown(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
-    msg.channel_id
-        .send_embed(
-            &ctx.http,
-            embed::build(format!(
-                "Doing something in {}",
-                emoji::get_bot_emote(&ctx, "p_countdown").await?
-            )),
-        )
+use super::playlist;
+
+pub async fn countdown(invocation: &Invocation<'_>) -> CommandResult {
+    invocation
+        .reply(embed::build(format!(
+            "Doing something in {}",
+            emoji::get_bot_emote(invocation.ctx, "p_countdown").await?
+        )))
         .await?;
 
     Ok(())
 }
 
-#[command]
-async fn play(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
-    let query = args.raw().collect::<Vec<&str>>().join(" ");
+pub async fn play(invocation: &Invocation<'_>, query: &str) -> CommandResult {
+    let ctx = invocation.ctx;
     if query.is_empty() {
-        msg.channel_id
-            .send_embed(
-                &ctx.http,
-                embed::error("You need to provide a search query!"),
+        invocation
+            .error(embed::error("You need to provide a search query!"))
+            .await?;
+
+        return Ok(());
+    }
+
+    // Searching can take longer than a slash command may go without a response
+    invocation.defer().await?;
+
+    if let Some(name) = query.strip_prefix(playlist::QUERY_PREFIX) {
+        return play_playlist(invocation, name.trim()).await;
+    }
+
+    let sources = {
+        let typemap = ctx.data.read().await;
+        typemap
+            .get::<SourceKey>()
+            .expect("Expected SourceKey in TypeMap.")
+            .clone()
+    };
+
+    let resolved = match sources.resolve(query).await {
+        Ok(resolved) => resolved,
+        Err(e) => {
+            invocation.error(embed::error(e.to_string())).await?;
+            return Ok(());
+        }
+    };
+
+    if resolved.tracks().is_empty() {
+        invocation.error(embed::error("No tracks found!")).await?;
+        return Ok(());
+    }
+
+    let Some(tracks) = choose_tracks(invocation, "Pick a track to play", resolved).await? else {
+        return Ok(());
+    };
+
+    let tracks = tracks
+        .into_iter()
+        .map(|track| track.requested_by(invocation.author().id))
+        .collect::<Vec<_>>();
+
+    // Single tracks are remembered, so they can be suggested when the user plays something again
+    if let [track] = tracks.as_slice() {
+        if let Err(why) = remember(invocation, track).await {
+            println!("Failed to remember a recent play: {:?}", why);
+        }
+    }
+
+    play_tracks(invocation, tracks).await
+}
+
+/// Plays the user's saved playlist with the name, or the guild's
+async fn play_playlist(invocation: &Invocation<'_>, name: &str) -> CommandResult {
+    let user = invocation.author().id;
+    let error = match playlist::find_with_tracks(invocation.ctx, invocation.guild_id(), user, name)
+        .await?
+    {
+        None => format!("There is no playlist called **{}**!", name),
+        Some((playlist, tracks)) if tracks.is_empty() => {
+            format!("The playlist **{}** is empty!", playlist.name)
+        }
+        Some((_, tracks)) => return play_tracks(invocation, tracks).await,
+    };
+
+    invocation.error(embed::error(error)).await?;
+    Ok(())
+}
+
+/// Remembers the track the user played, so it can be suggested to them later on
+async fn remember(invocation: &Invocation<'_>, track: &Track) -> Result<()> {
+    let Some(url) = &track.url else {
+        return Ok(());
+    };
+
+    let play = RecentPlay {
+        user_id: invocation.author().id.get() as i64,
+        query: url.clone(),
+        title: track.title.clone(),
+        played_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64,
+    };
+
+    let database = {
+        let typemap = invocation.ctx.data.read().await;
+        typemap
+            .get::<DatabaseKey>()
+            .expect("Expected DatabaseKey in TypeMap.")
+            .clone()
+    };
+
+    let mut connection = database.lock().await;
+    play.record(&mut connection)?;
+    Ok(())
+}
+
+async fn play_tracks(invocation: &Invocation<'_>, tracks: Vec<Track>) -> CommandResult {
+    let Some(added) = music::enqueue(invocation, &tracks).await? else {
+        return Ok(());
+    };
+
+    let mut reply = match tracks.as_slice() {
+        [track] => format!("Requested {}", track.linked_title()),
+        _ => format!("Requested **{}** tracks", added),
+    };
+
+    if added < tracks.len() {
+        reply.push_str(&format!(
+            "\n**{}** tracks were left out, they're too long or don't fit in the queue",
+            tracks.len() - added
+        ));
+    }
+
+    invocation.confirm(embed::build(reply)).await?;
+
+    Ok(())
+}
+
+/// The tracks that were asked for, letting the user pick one of the search results unless the first
+/// one is picked automatically. `None` if they didn't pick one in time.
+pub async fn choose_tracks(
+    invocation: &Invocation<'_>,
+    title: &str,
+    resolved: Resolved,
+) -> Result<Option<Vec<Track>>> {
+    let settings = {
+        let typemap = invocation.ctx.data.read().await;
+        typemap
+            .get::<ConfigKey>()
+            .expect("Expected ConfigKey in TypeMap.")
+            .music
+            .clone()
+    };
+
+    let tracks = match resolved {
+        Resolved::Search(tracks) if tracks.len() > 1 && !settings.auto_pick => {
+            let options = tracks
+                .iter()
+                .take(settings.search_results)
+                .map(|track| picker::PickOption::new(&track.title, describe(track)))
+                .collect::<Vec<_>>();
+
+            let timeout = Duration::from_secs(settings.pick_timeout);
+            match picker::pick(invocation, title, options, timeout).await? {
+                Some(index) => vec![tracks[index].clone()],
+                None => return Ok(None),
+            }
+        }
+        Resolved::Search(tracks) => vec![tracks[0].clone()],
+        Resolved::Direct(tracks) => tracks,
+    };
+
+    Ok(Some(tracks))
+}
+
+/// A short description of the track's author and length
+fn describe(track: &Track) -> String {
+    format!(
+        "{} • {}",
+        track.author.as_deref().unwrap_or("Unknown"),
+        track.display_duration()
+    )
+}
+
+/// How many tracks are shown on a single page of the queue
+const QUEUE_PAGE_SIZE: usize = 10;
+
+pub async fn skip(invocation: &Invocation<'_>) -> CommandResult {
+    let ctx = invocation.ctx;
+    let guild = invocation.guild_id();
+    let author = invocation.author().id;
+    let settings = {
+        let typemap = ctx.data.read().await;
+        typemap
+            .get::<ConfigKey>()
+            .expect("Expected ConfigKey in TypeMap.")
+            .music
+            .clone()
+    };
+
+    let manager = music::get_manager(ctx, guild).await;
+    let mut music = manager.lock().await;
+
+    let Some(track) = music.now_playing().cloned() else {
+        invocation
+            .error(embed::error("Nothing is playing right now!"))
+            .await?;
+
+        return Ok(());
+    };
+
+    let instant = !settings.vote_skip
+        || track.requester == Some(author)
+        || music::is_privileged(invocation).await?;
+
+    if instant {
+        music.skip().await;
+        invocation
+            .reply(embed::build(format!("Skipped {}", track.linked_title())))
+            .await?;
+
+        return Ok(());
+    }
+
+    let listeners = music::get_listeners(ctx, guild);
+    if !listeners.contains(&author) {
+        invocation
+            .error(embed::error("You need to be listening to vote to skip!"))
+            .await?;
+
+        return Ok(());
+    }
+
+    music.vote_skip(author);
+
+    // Listeners who voted and then left don't count anymore
+    let votes = music
+        .skip_votes()
+        .iter()
+        .filter(|user| listeners.contains(user))
+        .count();
+    let needed = (listeners.len() * settings.skip_threshold as usize)
+        .div_ceil(100)
+        .max(1);
+
+    let embed = if votes >= needed {
+        embed::build(format!(
+            "Vote passed ({}/{}), skipped {}",
+            votes,
+            needed,
+            track.linked_title()
+        ))
+    } else {
+        embed::build(format!(
+            "Voting to skip {}: **{}/{}** votes",
+            track.linked_title(),
+            votes,
+            needed
+        ))
+    };
+
+    // Keep the progress in a single message instead of sending one per vote
+    let progress = match music.skip_message() {
+        Some((channel, message)) => channel
+            .edit_message(&ctx.http, message, EditMessage::new().embed(embed.clone()))
+            .await
+            .ok(),
+        None => None,
+    };
+
+    match progress {
+        Some(_) => invocation.confirm(embed::build("Voted to skip")).await?,
+        None => {
+            let message = invocation.send(embed, Vec::new()).await?;
+            music.set_skip_message(message.channel_id, message.id);
+        }
+    }
+
+    if votes >= needed {
+        music.skip().await;
+    }
+
+    Ok(())
+}
+
+pub async fn forceskip(invocation: &Invocation<'_>) -> CommandResult {
+    if !music::require_dj(invocation).await? {
+        return Ok(());
+    }
+
+    let manager = music::get_manager(invocation.ctx, invocation.guild_id()).await;
+    let mut music = manager.lock().await;
+
+    let Some(track) = music.now_playing().cloned() else {
+        invocation
+            .error(embed::error("Nothing is playing right now!"))
+            .await?;
+
+        return Ok(());
+    };
+
+    music.skip().await;
+    invocation
+        .reply(embed::build(format!(
+            "Force skipped {}",
+            track.linked_title()
+        )))
+        .await?;
+
+    Ok(())
+}
+
+pub async fn queue(invocation: &Invocation<'_>, page: Option<usize>) -> CommandResult {
+    let manager = music::get_manager(invocation.ctx, invocation.guild_id()).await;
+
+    // The queue isn't kept locked while the user flips through the pages
+    let paginator = {
+        let music = manager.lock().await;
+
+        if music.is_empty() {
+            invocation
+                .reply(embed::build("The queue is empty!"))
+                .await?;
+            return Ok(());
+        }
+
+        let now_playing = match music.now_playing() {
+            Some(track) => format!("Now playing **{}**\n\n", track.title),
+            None => String::new(),
+        };
+
+        let total = music
+            .tracks()
+            .iter()
+            .filter_map(|track| track.duration)
+            .sum::<Duration>();
+
+        let lines = music.tracks().iter().enumerate().map(|(i, track)| {
+            format!(
+                "`{}.` {} `{}`",
+                i + 1,
+                track.linked_title(),
+                track.display_duration()
             )
+        });
+
+        Paginator::new(
+            format!(
+                "{}**Queue** ({} tracks, {})\n",
+                now_playing,
+                music.len(),
+                format_duration(total)
+            ),
+            lines,
+            QUEUE_PAGE_SIZE,
+        )
+    };
+
+    let page = page.unwrap_or(1);
+    if page == 0 || page > paginator.page_count() {
+        invocation
+            .error(embed::error(format!(
+                "Page {} doesn't exist, the queue only has {} page(s)",
+                page,
+                paginator.page_count()
+            )))
             .await?;
 
         return Ok(());
     }
 
-    let mut typemap = ctx.data.write().await;
-    let videos = music::query_youtube(&typemap, &query).await;
+    paginator.show(invocation, page - 1).await?;
+    Ok(())
+}
 
-    let http_client = typemap
-        .get::<HttpKey>()
-        .expect("Expected HttpKey in TypeMap.")
-        .clone();
+pub async fn remove(invocation: &Invocation<'_>, position: Option<usize>) -> CommandResult {
+    if !music::require_dj(invocation).await? {
+        return Ok(());
+    }
 
-    let songbird = typemap
-        .get::<SongbirdKey>()
-        .expect("Expected SongbirdKey in TypeMap.")
-        .clone();
+    let Some(position) = position else {
+        invocation
+            .error(embed::error(
+                "You need to provide the position of the track to remove!",
+            ))
+            .await?;
 
-    let manager = typemap
-        .get_mut::<GuildContext>()
-        .expect("Expected GuildManager in TypeMap.");
+        return Ok(());
+    };
 
-    let data = manager.get(&msg.guild_id.unwrap());
-    let videos = match videos {
-        Ok(v) => v,
+    let manager = music::get_manager(invocation.ctx, invocation.guild_id()).await;
+    let mut music = manager.lock().await;
+
+    let removed = match to_index(position, &music) {
+        Ok(index) => music.remove(index),
         Err(e) => {
-            msg.channel_id
-                .send_embed(&ctx.http, embed::error(&format!("{}", e)))
-                .await?;
+            invocation.error(embed::error(e)).await?;
+            return Ok(());
+        }
+    };
+
+    if let Some(track) = removed {
+        invocation
+            .reply(embed::build(format!(
+                "Removed **{}** from the queue",
+                track.title
+            )))
+            .await?;
+    }
+
+    Ok(())
+}
+
+pub async fn move_track(
+    invocation: &Invocation<'_>,
+    positions: Option<(usize, usize)>,
+) -> CommandResult {
+    if !music::require_dj(invocation).await? {
+        return Ok(());
+    }
+
+    let Some((from, to)) = positions else {
+        invocation
+            .error(embed::error(
+                "Usage: `move <from> <to>`, using the positions shown in the queue",
+            ))
+            .await?;
+
+        return Ok(());
+    };
 
+    let manager = music::get_manager(invocation.ctx, invocation.guild_id()).await;
+    let mut music = manager.lock().await;
+
+    let moved = match (to_index(from, &music), to_index(to, &music)) {
+        (Ok(from), Ok(to)) => music.move_track(from, to).map(|track| track.title.clone()),
+        (Err(e), _) | (_, Err(e)) => {
+            invocation.error(embed::error(e)).await?;
             return Ok(());
         }
     };
 
-    let video = if videos.is_empty() {
-        msg.channel_id
-            .send_embed(&ctx.http, embed::error("No tracks found!"))
+    if let Some(title) = moved {
+        invocation
+            .reply(embed::build(format!(
+                "Moved **{}** to position {}",
+                title, to
+            )))
+            .await?;
+    }
+
+    Ok(())
+}
+
+pub async fn clear(invocation: &Invocation<'_>) -> CommandResult {
+    if !music::require_dj(invocation).await? {
+        return Ok(());
+    }
+
+    let manager = music::get_manager(invocation.ctx, invocation.guild_id()).await;
+    let mut music = manager.lock().await;
+
+    let removed = music.len();
+    music.clear();
+
+    invocation
+        .reply(embed::build(format!(
+            "Cleared {} tracks from the queue",
+            removed
+        )))
+        .await?;
+
+    Ok(())
+}
+
+pub async fn shuffle(invocation: &Invocation<'_>) -> CommandResult {
+    if !music::require_dj(invocation).await? {
+        return Ok(());
+    }
+
+    let manager = music::get_manager(invocation.ctx, invocation.guild_id()).await;
+    let mut music = manager.lock().await;
+
+    if music.len() < 2 {
+        invocation
+            .error(embed::error(
+                "There aren't enough tracks in the queue to shuffle!",
+            ))
             .await?;
 
         return Ok(());
-    } else if videos.len() > 1 {
-        // for now, default to just the first one
-        videos[0].clone()
+    }
+
+    music.shuffle();
+    player::refresh(invocation.ctx, invocation.guild_id());
+    invocation
+        .reply(embed::build(format!(
+            "Shuffled {} tracks in the queue",
+            music.len()
+        )))
+        .await?;
+
+    Ok(())
+}
+
+pub async fn fair(invocation: &Invocation<'_>) -> CommandResult {
+    if !music::require_dj(invocation).await? {
+        return Ok(());
+    }
+
+    let ctx = invocation.ctx;
+    let guild = invocation.guild_id();
+    let mut settings = settings::get_settings(ctx, guild).await?;
+    settings.fair_queue = !settings.fair_queue;
+    settings::save_settings(ctx, &settings).await?;
+
+    let manager = music::get_manager(ctx, guild).await;
+    manager.lock().await.set_fair(settings.fair_queue);
+
+    let embed = if settings.fair_queue {
+        embed::build("Fair queue is on, everyone's tracks will play in turns")
     } else {
-        videos[0].clone()
+        embed::build("Fair queue is off, tracks will play in the order they were added")
     };
 
-    let track = Track::from_youtube(video);
+    invocation.reply(embed).await?;
+    Ok(())
+}
 
-    music::ensure_connected(&ctx, Arc::clone(&songbird), Arc::clone(&data.music), &msg)
-        .await
-        .expect("Failed to connect to voice channel");
+pub async fn previous(invocation: &Invocation<'_>) -> CommandResult {
+    let manager = music::get_manager(invocation.ctx, invocation.guild_id()).await;
+    let mut music = manager.lock().await;
 
-    {
-        let mut music = data.music.lock().await;
+    match music.previous().await {
+        Some(track) => {
+            invocation
+                .confirm(embed::build(format!(
+                    "Playing {} again",
+                    track.linked_title()
+                )))
+                .await?
+        }
+        None => {
+            invocation
+                .error(embed::error("There is no previous track to play!"))
+                .await?
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn loop_mode(invocation: &Invocation<'_>, mode: Option<&str>) -> CommandResult {
+    let manager = music::get_manager(invocation.ctx, invocation.guild_id()).await;
+    let mut music = manager.lock().await;
 
-        if !music.has_handler() {
-            let handler = Box::new(MusicHandler::new(
-                ctx.clone(),
-                Arc::clone(&songbird),
-                Arc::from(http_client),
-                msg.guild_id.unwrap(),
-                msg.channel_id,
-            ));
+    let Some(mode) = mode else {
+        invocation
+            .reply(embed::build(format!(
+                "Currently looping: **{}**\nUsage: `loop none|track|queue`",
+                music.get_loop()
+            )))
+            .await?;
+
+        return Ok(());
+    };
+
+    if !music::require_dj(invocation).await? {
+        return Ok(());
+    }
 
-            music.event_handler(handler);
+    match mode.parse::<Loop>() {
+        Ok(mode) => {
+            music.set_loop(mode);
+            player::refresh(invocation.ctx, invocation.guild_id());
+            invocation
+                .reply(embed::build(format!("Now looping: **{}**", mode)))
+                .await?;
+        }
+        Err(e) => {
+            invocation.error(embed::error(e)).await?;
         }
+    }
+
+    Ok(())
+}
 
-        music.add(&track).await;
+pub async fn autoplay(invocation: &Invocation<'_>) -> CommandResult {
+    if !music::require_dj(invocation).await? {
+        return Ok(());
     }
 
+    let manager = music::get_manager(invocation.ctx, invocation.guild_id()).await;
+    let mut music = manager.lock().await;
+
+    let autoplay = !music.autoplay();
+    music.set_autoplay(autoplay).await;
+
+    let embed = if autoplay {
+        embed::build("Autoplay is on, related tracks will play when the queue runs out")
+    } else {
+        embed::build("Autoplay is off, the music stops when the queue runs out")
+    };
+
+    invocation.reply(embed).await?;
     Ok(())
 }
 
-#[command]
-pub async fn skip(ctx: &Context, msg: &Message) -> CommandResult {
-    let mut typemap = ctx.data.write().await;
-    let manager = typemap
-        .get_mut::<GuildContext>()
-        .expect("Expected GuildManager in TypeMap.");
+pub async fn nowplaying(invocation: &Invocation<'_>) -> CommandResult {
+    let manager = music::get_manager(invocation.ctx, invocation.guild_id()).await;
+    let music = manager.lock().await;
 
-    let data = manager.get(&msg.guild_id.unwrap());
-    let mut music = data.music.lock().await;
+    let Some(track) = music.now_playing() else {
+        invocation
+            .error(embed::error("Nothing is playing right now!"))
+            .await?;
 
-    music.skip().await;
+        return Ok(());
+    };
+
+    let status = match music.playback_state().await {
+        Ok((position, paused)) => format!(
+            "{} {}/{}",
+            if paused { "Paused at" } else { "Playing at" },
+            format_duration(position),
+            track.display_duration()
+        ),
+        Err(_) => "Loading".to_string(),
+    };
+
+    let requester = match track.requester {
+        Some(requester) => format!("\nRequested by {}", requester.mention()),
+        None => String::new(),
+    };
+
+    let filters = if music.filters().is_active() {
+        format!("\nFilters: {}", music.filters())
+    } else {
+        String::new()
+    };
+
+    let embed = embed::build(format!(
+        "{} Now playing {}{}\n{} | Volume: {}% | Loop: {} | Up next: {} tracks{}",
+        emoji::get_bot_emote(invocation.ctx, "p_music").await?,
+        track.linked_title(),
+        requester,
+        status,
+        (music.volume() * 100.0).round(),
+        music.get_loop(),
+        music.len(),
+        filters
+    ));
+
+    let embed = match &track.thumbnail {
+        Some(thumbnail) => embed.thumbnail(thumbnail),
+        None => embed,
+    };
+
+    invocation.reply(embed).await?;
+
+    Ok(())
+}
+
+pub async fn pause(invocation: &Invocation<'_>) -> CommandResult {
+    let manager = music::get_manager(invocation.ctx, invocation.guild_id()).await;
+    let music = manager.lock().await;
+
+    match music.pause() {
+        Ok(_) => {
+            player::refresh(invocation.ctx, invocation.guild_id());
+            invocation
+                .reply(embed::build("Paused the current track"))
+                .await?
+        }
+        Err(e) => invocation.error(embed::error(e.to_string())).await?,
+    }
+
+    Ok(())
+}
+
+pub async fn resume(invocation: &Invocation<'_>) -> CommandResult {
+    let manager = music::get_manager(invocation.ctx, invocation.guild_id()).await;
+    let music = manager.lock().await;
+
+    match music.resume() {
+        Ok(_) => {
+            player::refresh(invocation.ctx, invocation.guild_id());
+            invocation
+                .reply(embed::build("Resumed the current track"))
+                .await?
+        }
+        Err(e) => invocation.error(embed::error(e.to_string())).await?,
+    }
+
+    Ok(())
+}
+
+pub async fn seek(invocation: &Invocation<'_>, timestamp: Option<&str>) -> CommandResult {
+    let Some(position) = timestamp.and_then(parse_timestamp) else {
+        invocation
+            .error(embed::error(
+                "Usage: `seek <timestamp>`, for example `seek 1:30`",
+            ))
+            .await?;
+
+        return Ok(());
+    };
+
+    let manager = music::get_manager(invocation.ctx, invocation.guild_id()).await;
+    let music = manager.lock().await;
+
+    match music.seek(position).await {
+        Ok(position) => {
+            player::refresh(invocation.ctx, invocation.guild_id());
+            invocation
+                .reply(embed::build(format!(
+                    "Seeked to {}",
+                    format_duration(position)
+                )))
+                .await?
+        }
+        Err(e) => {
+            invocation
+                .error(embed::error(format!("Unable to seek: {}", e)))
+                .await?
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn volume(invocation: &Invocation<'_>, volume: Option<u32>) -> CommandResult {
+    let manager = music::get_manager(invocation.ctx, invocation.guild_id()).await;
+    let mut music = manager.lock().await;
+
+    let Some(volume) = volume else {
+        invocation
+            .reply(embed::build(format!(
+                "The volume is at **{}%**\nUsage: `volume <0-200>`",
+                (music.volume() * 100.0).round()
+            )))
+            .await?;
+
+        return Ok(());
+    };
+
+    if !music::require_dj(invocation).await? {
+        return Ok(());
+    }
+
+    if volume > 200 {
+        invocation
+            .error(embed::error("The volume must be between 0 and 200!"))
+            .await?;
+
+        return Ok(());
+    }
+
+    match music.set_volume(volume as f32 / 100.0) {
+        Ok(_) => {
+            player::refresh(invocation.ctx, invocation.guild_id());
+            invocation
+                .reply(embed::build(format!("Set the volume to **{}%**", volume)))
+                .await?
+        }
+        Err(e) => invocation.error(embed::error(e.to_string())).await?,
+    }
+
+    Ok(())
+}
+
+/// The longest crossfade, in seconds
+pub const MAX_CROSSFADE: u64 = 12;
+
+pub async fn crossfade(invocation: &Invocation<'_>, seconds: Option<u64>) -> CommandResult {
+    let ctx = invocation.ctx;
+    let manager = music::get_manager(ctx, invocation.guild_id()).await;
+
+    let Some(seconds) = seconds else {
+        let crossfade = manager.lock().await.crossfade();
+        invocation
+            .reply(embed::build(format!(
+                "The crossfade is **{}s**\nUsage: `crossfade <0-{}>`",
+                crossfade.as_secs(),
+                MAX_CROSSFADE
+            )))
+            .await?;
+
+        return Ok(());
+    };
+
+    if !music::require_dj(invocation).await? {
+        return Ok(());
+    }
+
+    if seconds > MAX_CROSSFADE {
+        invocation
+            .error(embed::error(format!(
+                "The crossfade must be between 0 and {} seconds!",
+                MAX_CROSSFADE
+            )))
+            .await?;
+
+        return Ok(());
+    }
+
+    let mut settings = settings::get_settings(ctx, invocation.guild_id()).await?;
+    settings.crossfade = seconds as i32;
+    settings::save_settings(ctx, &settings).await?;
+    manager.lock().await.set_crossfade(settings.crossfade());
+
+    let embed = match seconds {
+        0 => embed::build("Crossfade is off, tracks will play one after another"),
+        seconds => embed::build(format!("Tracks will now crossfade over **{}s**", seconds)),
+    };
+
+    invocation.reply(embed).await?;
+    Ok(())
+}
+
+const FILTER_USAGE: &str = "Usage: `filter bassboost [dB]|nightcore|vaporwave|speed <0.5-2>|eq <band> <dB>|eq reset|reset`";
+/// How much the bass is boosted when no gain is given
+const DEFAULT_BASS_BOOST: f32 = 6.0;
+
+/// Changes the filters, the arguments are the name of the filter followed by its values
+pub async fn filter(invocation: &Invocation<'_>, mut args: Args) -> CommandResult {
+    let manager = music::get_manager(invocation.ctx, invocation.guild_id()).await;
+
+    let Ok(name) = args.single::<String>() else {
+        let filters = manager.lock().await.filters().to_string();
+        invocation
+            .reply(embed::build(format!(
+                "Active filters: **{}**\n{}",
+                filters, FILTER_USAGE
+            )))
+            .await?;
+
+        return Ok(());
+    };
+
+    if !music::require_dj(invocation).await? {
+        return Ok(());
+    }
+
+    let mut music = manager.lock().await;
+    let mut filters = music.filters().clone();
+
+    match change_filter(&mut filters, &name.to_lowercase(), &mut args) {
+        Ok(()) => {
+            music.set_filters(filters).await;
+            invocation
+                .reply(embed::build(format!(
+                    "Active filters: **{}**",
+                    music.filters()
+                )))
+                .await?;
+        }
+        Err(e) => {
+            invocation.error(embed::error(e)).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Applies the filter command to the filters, the error is meant for the user
+fn change_filter(filters: &mut Filters, name: &str, args: &mut Args) -> Result<(), String> {
+    match name {
+        "bassboost" | "bass" => {
+            let gain = args.single::<f32>().unwrap_or(DEFAULT_BASS_BOOST);
+            if !(0.0..=filter::MAX_GAIN).contains(&gain) {
+                return Err(format!(
+                    "The bass boost must be between 0 and {} dB!",
+                    filter::MAX_GAIN
+                ));
+            }
+
+            filters.bass_boost = gain;
+        }
+        "nightcore" | "vaporwave" => {
+            let style = match name {
+                "nightcore" => Style::Nightcore,
+                _ => Style::Vaporwave,
+            };
+
+            // Using the same style again turns it off
+            filters.style = (filters.style != Some(style)).then_some(style);
+        }
+        "speed" => {
+            let speed = args
+                .single::<f32>()
+                .map_err(|_| "Usage: `filter speed <0.5-2>`".to_string())?;
+            if !(filter::MIN_SPEED..=filter::MAX_SPEED).contains(&speed) {
+                return Err(format!(
+                    "The speed must be between {} and {}!",
+                    filter::MIN_SPEED,
+                    filter::MAX_SPEED
+                ));
+            }
+
+            filters.speed = speed;
+        }
+        "eq" | "equalizer" => {
+            if args.current() == Some("reset") {
+                filters.equalizer = Filters::default().equalizer;
+                return Ok(());
+            }
+
+            let bands = filter::EQ_BANDS
+                .iter()
+                .enumerate()
+                .map(|(band, frequency)| format!("{}: {}Hz", band + 1, frequency))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let usage = format!("Usage: `filter eq <band> <dB>`\nBands: {}", bands);
+
+            let band = args.single::<usize>().map_err(|_| usage.clone())?;
+            let gain = args.single::<f32>().map_err(|_| usage.clone())?;
+            if band == 0 || band > filter::EQ_BANDS.len() {
+                return Err(usage);
+            }
+            if gain.abs() > filter::MAX_GAIN {
+                return Err(format!(
+                    "The gain must be between -{0} and {0} dB!",
+                    filter::MAX_GAIN
+                ));
+            }
+
+            filters.equalizer[band - 1] = gain;
+        }
+        "reset" | "clear" | "off" => *filters = Filters::default(),
+        _ => return Err(FILTER_USAGE.to_string()),
+    }
+
+    Ok(())
+}
+
+pub async fn stop(invocation: &Invocation<'_>) -> CommandResult {
+    if !music::require_dj(invocation).await? {
+        return Ok(());
+    }
+
+    let manager = music::get_manager(invocation.ctx, invocation.guild_id()).await;
+    let mut music = manager.lock().await;
+
+    music.stop().await;
+
+    invocation
+        .reply(embed::build("Stopped playing and cleared the queue"))
+        .await?;
+
+    Ok(())
+}
+
+/// Turns a 1-based queue position into an index, with a readable error when it's out of range
+fn to_index(position: usize, music: &MusicManager) -> Result<usize, String> {
+    if music.is_empty() {
+        return Err("The queue is empty!".to_string());
+    }
+
+    if position == 0 || position > music.len() {
+        return Err(format!(
+            "There is no track at position {}, the queue has {} tracks",
+            position,
+            music.len()
+        ));
+    }
+
+    Ok(position - 1)
+}
+
+/// Shows or changes the DJ role, the role is a mention, an id, or `none` to remove it
+pub async fn djrole(invocation: &Invocation<'_>, role: Option<&str>) -> CommandResult {
+    let ctx = invocation.ctx;
+    let mut settings = settings::get_settings(ctx, invocation.guild_id()).await?;
+
+    let Some(role) = role else {
+        let role = match settings.dj_role() {
+            Some(role) => format!("The DJ role is {}", role.mention()),
+            None => "There is no DJ role, everyone can use every music command".to_string(),
+        };
+
+        invocation
+            .reply(embed::build(format!(
+                "{}\nUsage: `djrole <role|none>`",
+                role
+            )))
+            .await?;
+
+        return Ok(());
+    };
+
+    let member = invocation.member().await?;
+    if !roles::has_permission(ctx, &member, Permissions::MANAGE_GUILD).await {
+        invocation
+            .error(embed::error(
+                "You need the Manage Server permission to change the DJ role!",
+            ))
+            .await?;
+
+        return Ok(());
+    }
+
+    let role = match role.trim() {
+        "none" | "off" => None,
+        role => match role.parse::<RoleId>() {
+            Ok(role) => Some(role),
+            Err(_) => {
+                invocation
+                    .error(embed::error(
+                        "Mention the role or use its id to set the DJ role!",
+                    ))
+                    .await?;
+
+                return Ok(());
+            }
+        },
+    };
+
+    settings.set_dj_role(role);
+    settings::save_settings(ctx, &settings).await?;
+
+    let embed = match role {
+        Some(role) => embed::build(format!("Set the DJ role to {}", role.mention())),
+        None => embed::build("Removed the DJ role, everyone can use every music command"),
+    };
+
+    invocation.reply(embed).await?;
+    Ok(())
+}
+
+pub async fn always_connected(invocation: &Invocation<'_>) -> CommandResult {
+    let ctx = invocation.ctx;
+    let member = invocation.member().await?;
+    if !roles::has_permission(ctx, &member, Permissions::MANAGE_GUILD).await {
+        invocation
+            .error(embed::error(
+                "You need the Manage Server permission to toggle 24/7 mode!",
+            ))
+            .await?;
+
+        return Ok(());
+    }
+
+    let mut settings = settings::get_settings(ctx, invocation.guild_id()).await?;
+    settings.always_connected = !settings.always_connected;
+    settings::save_settings(ctx, &settings).await?;
+
+    let embed = if settings.always_connected {
+        embed::build("24/7 mode is on, I'll stay in the voice channel even when idle")
+    } else {
+        embed::build("24/7 mode is off, I'll leave the voice channel when idle")
+    };
+
+    invocation.reply(embed).await?;
+    Ok(())
+}
+
+pub async fn normalize(invocation: &Invocation<'_>) -> CommandResult {
+    let ctx = invocation.ctx;
+    let member = invocation.member().await?;
+    if !roles::has_permission(ctx, &member, Permissions::MANAGE_GUILD).await {
+        invocation
+            .error(embed::error(
+                "You need the Manage Server permission to toggle normalization!",
+            ))
+            .await?;
+
+        return Ok(());
+    }
+
+    let mut settings = settings::get_settings(ctx, invocation.guild_id()).await?;
+    settings.normalize = !settings.normalize;
+    settings::save_settings(ctx, &settings).await?;
+
+    let embed = if settings.normalize {
+        embed::build("Normalization is on, tracks will play at about the same loudness from the next track on")
+    } else {
+        embed::build("Normalization is off, tracks will play at their original loudness")
+    };
+
+    invocation.reply(embed).await?;
     Ok(())
 }
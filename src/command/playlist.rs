@@ -0,0 +1,724 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Result;
+use diesel::SqliteConnection;
+use rand::seq::SliceRandom;
+use serenity::{
+    all::{GuildId, Permissions, RoleId, UserId},
+    client::Context,
+    framework::standard::CommandResult,
+    prelude::Mentionable,
+};
+use tokio::sync::Mutex;
+
+use crate::{
+    discord::{invocation::Invocation, roles},
+    guilds::music::{
+        source::{Metadata, SourceRegistry},
+        track::Track,
+    },
+    helper::{embed, helper::format_duration, music, settings},
+    models::playlist::{Owner, Playlist, PlaylistTrack},
+    DatabaseKey, SourceKey,
+};
+
+use super::music::choose_tracks;
+
+/// Plays a saved playlist when put in front of its name in `play`, such as `play playlist:chill`
+pub const QUERY_PREFIX: &str = "playlist:";
+const PLAYLIST_USAGE: &str = "Usage: `playlist create|add|remove|show|play|delete|save [guild] <name>`\nPut `guild` before the name to use the guild's playlists instead of your own";
+/// The longest name a playlist can have
+pub const MAX_NAME_LENGTH: usize = 32;
+/// The most tracks a playlist can hold
+const MAX_PLAYLIST_SIZE: usize = 500;
+/// How many tracks are shown on a single page of a playlist
+const PLAYLIST_PAGE_SIZE: usize = 10;
+
+pub async fn usage(invocation: &Invocation<'_>) -> CommandResult {
+    invocation.reply(embed::build(PLAYLIST_USAGE)).await?;
+    Ok(())
+}
+
+pub async fn create(invocation: &Invocation<'_>, guild: bool, name: Option<&str>) -> CommandResult {
+    let ctx = invocation.ctx;
+    let owner = owner(invocation, guild);
+    let Some(name) = check_name(invocation, name).await? else {
+        return Ok(());
+    };
+
+    if !require_editor(invocation, owner).await? {
+        return Ok(());
+    }
+
+    let database = database(ctx).await;
+    let mut connection = database.lock().await;
+    if Playlist::find(&mut connection, owner, name)?.is_some() {
+        invocation
+            .error(embed::error(format!(
+                "The playlist **{}** already exists!",
+                name
+            )))
+            .await?;
+
+        return Ok(());
+    }
+
+    Playlist::create(&mut connection, owner, name)?;
+    invocation
+        .reply(embed::build(format!(
+            "Created the playlist **{}**, add tracks with `playlist add {}{} <query>`",
+            name,
+            scope_prefix(owner),
+            name
+        )))
+        .await?;
+
+    Ok(())
+}
+
+pub async fn add(
+    invocation: &Invocation<'_>,
+    guild: bool,
+    name: Option<&str>,
+    query: &str,
+) -> CommandResult {
+    let ctx = invocation.ctx;
+    let owner = owner(invocation, guild);
+    let Some(playlist) = find_editable(invocation, owner, name).await? else {
+        return Ok(());
+    };
+
+    let query = query.trim();
+    if query.is_empty() {
+        invocation
+            .error(embed::error("You need to provide a search query!"))
+            .await?;
+
+        return Ok(());
+    }
+
+    // Searching can take longer than a slash command may go without a response
+    invocation.defer().await?;
+
+    let sources = sources(ctx).await;
+    let resolved = match sources.resolve(query).await {
+        Ok(resolved) if resolved.tracks().is_empty() => {
+            invocation.error(embed::error("No tracks found!")).await?;
+            return Ok(());
+        }
+        Ok(resolved) => resolved,
+        Err(e) => {
+            invocation.error(embed::error(e.to_string())).await?;
+            return Ok(());
+        }
+    };
+
+    let title = format!("Pick a track to add to {}", playlist.name);
+    let Some(tracks) = choose_tracks(invocation, &title, resolved).await? else {
+        return Ok(());
+    };
+
+    let database = database(ctx).await;
+    let mut connection = database.lock().await;
+    let mut saved = playlist.tracks(&mut connection)?;
+    if saved.len() + tracks.len() > MAX_PLAYLIST_SIZE {
+        invocation
+            .error(embed::error(format!(
+                "A playlist can't hold more than {} tracks!",
+                MAX_PLAYLIST_SIZE
+            )))
+            .await?;
+
+        return Ok(());
+    }
+
+    saved.extend(tracks.iter().map(to_saved));
+    playlist.set_tracks(&mut connection, saved)?;
+
+    let added = match tracks.as_slice() {
+        [track] => track.linked_title(),
+        tracks => format!("**{}** tracks", tracks.len()),
+    };
+
+    invocation
+        .reply(embed::build(format!(
+            "Added {} to **{}**",
+            added, playlist.name
+        )))
+        .await?;
+
+    Ok(())
+}
+
+pub async fn remove(
+    invocation: &Invocation<'_>,
+    guild: bool,
+    name: Option<&str>,
+    position: Option<usize>,
+) -> CommandResult {
+    let ctx = invocation.ctx;
+    let owner = owner(invocation, guild);
+    let Some(playlist) = find_editable(invocation, owner, name).await? else {
+        return Ok(());
+    };
+
+    let Some(position) = position else {
+        invocation
+            .error(embed::error(
+                "You need to provide the position of the track to remove!",
+            ))
+            .await?;
+
+        return Ok(());
+    };
+
+    let database = database(ctx).await;
+    let mut connection = database.lock().await;
+    let mut tracks = playlist.tracks(&mut connection)?;
+    if position == 0 || position > tracks.len() {
+        invocation
+            .error(embed::error(format!(
+                "There is no track at position {}, the playlist has {} tracks",
+                position,
+                tracks.len()
+            )))
+            .await?;
+
+        return Ok(());
+    }
+
+    let removed = tracks.remove(position - 1);
+    playlist.set_tracks(&mut connection, tracks)?;
+
+    invocation
+        .reply(embed::build(format!(
+            "Removed **{}** from **{}**",
+            removed.title, playlist.name
+        )))
+        .await?;
+
+    Ok(())
+}
+
+/// Shows a page of the playlist, or the names of the playlists when no name is given
+pub async fn show(
+    invocation: &Invocation<'_>,
+    guild: bool,
+    name: Option<&str>,
+    page: Option<usize>,
+) -> CommandResult {
+    let ctx = invocation.ctx;
+    let owner = owner(invocation, guild);
+    if name.is_none() {
+        return list(invocation, owner).await;
+    }
+
+    let Some(playlist) = find_playable(invocation, owner, name).await? else {
+        return Ok(());
+    };
+
+    let database = database(ctx).await;
+    let tracks = {
+        let mut connection = database.lock().await;
+        playlist.tracks(&mut connection)?
+    };
+
+    if tracks.is_empty() {
+        invocation
+            .reply(embed::build(format!(
+                "The playlist **{}** is empty!",
+                playlist.name
+            )))
+            .await?;
+
+        return Ok(());
+    }
+
+    let pages = (tracks.len() + PLAYLIST_PAGE_SIZE - 1) / PLAYLIST_PAGE_SIZE;
+    let page = page.unwrap_or(1);
+    if page == 0 || page > pages {
+        invocation
+            .error(embed::error(format!(
+                "Page {} doesn't exist, the playlist only has {} page(s)",
+                page, pages
+            )))
+            .await?;
+
+        return Ok(());
+    }
+
+    let body = tracks
+        .iter()
+        .enumerate()
+        .skip((page - 1) * PLAYLIST_PAGE_SIZE)
+        .take(PLAYLIST_PAGE_SIZE)
+        .map(|(i, track)| {
+            let title = match &track.url {
+                Some(url) => format!("[**{}**]({})", track.title, url),
+                None => format!("**{}**", track.title),
+            };
+
+            let duration = match track.duration {
+                Some(seconds) => format_duration(Duration::from_secs(seconds as u64)),
+                None => "--:--".to_string(),
+            };
+
+            format!("`{}.` {} `{}`", i + 1, title, duration)
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    let total = tracks
+        .iter()
+        .filter_map(|track| track.duration)
+        .map(|seconds| Duration::from_secs(seconds as u64))
+        .sum::<Duration>();
+
+    invocation
+        .reply(embed::build(format!(
+            "**{}** ({} tracks, {})\n{}\n\nPage {}/{}",
+            playlist.name,
+            tracks.len(),
+            format_duration(total),
+            body,
+            page,
+            pages
+        )))
+        .await?;
+
+    Ok(())
+}
+
+/// Shows the names of the playlists of the author and the guild
+async fn list(invocation: &Invocation<'_>, owner: Owner) -> CommandResult {
+    let mut owners = vec![owner];
+    if let Owner::User(_) = owner {
+        owners.push(Owner::Guild(invocation.guild_id()));
+    }
+
+    let database = database(invocation.ctx).await;
+    let mut sections = Vec::new();
+    {
+        let mut connection = database.lock().await;
+        for owner in owners {
+            let names = Playlist::list(&mut connection, owner)?
+                .into_iter()
+                .map(|playlist| format!("`{}`", playlist.name))
+                .collect::<Vec<_>>();
+
+            if names.is_empty() {
+                continue;
+            }
+
+            let heading = match owner {
+                Owner::User(_) => "Your playlists",
+                Owner::Guild(_) => "Guild playlists",
+            };
+            sections.push(format!("**{}**\n{}", heading, names.join(", ")));
+        }
+    }
+
+    let embed = if sections.is_empty() {
+        embed::build(format!("There are no playlists yet\n{}", PLAYLIST_USAGE))
+    } else {
+        embed::build(sections.join("\n\n"))
+    };
+
+    invocation.reply(embed).await?;
+    Ok(())
+}
+
+/// Queues the tracks of the playlist, the only option is `shuffle` to queue them in a random order
+pub async fn play(
+    invocation: &Invocation<'_>,
+    guild: bool,
+    name: Option<&str>,
+    option: Option<&str>,
+) -> CommandResult {
+    let owner = owner(invocation, guild);
+    let Some(playlist) = find_playable(invocation, owner, name).await? else {
+        return Ok(());
+    };
+
+    let shuffle = match option {
+        Some("shuffle" | "--shuffle") => true,
+        Some(option) => {
+            invocation
+                .error(embed::error(format!(
+                    "Unknown option `{}`\nUsage: `playlist play [guild] <name> [shuffle]`",
+                    option
+                )))
+                .await?;
+
+            return Ok(());
+        }
+        None => false,
+    };
+
+    let mut tracks = load_tracks(invocation.ctx, &playlist, invocation.author().id).await?;
+
+    if tracks.is_empty() {
+        invocation
+            .error(embed::error(format!(
+                "The playlist **{}** is empty!",
+                playlist.name
+            )))
+            .await?;
+
+        return Ok(());
+    }
+
+    if shuffle {
+        tracks.shuffle(&mut rand::thread_rng());
+    }
+
+    music::enqueue(invocation, &tracks).await?;
+    Ok(())
+}
+
+pub async fn delete(invocation: &Invocation<'_>, guild: bool, name: Option<&str>) -> CommandResult {
+    let owner = owner(invocation, guild);
+    let Some(playlist) = find_editable(invocation, owner, name).await? else {
+        return Ok(());
+    };
+
+    {
+        let database = database(invocation.ctx).await;
+        let mut connection = database.lock().await;
+        playlist.delete(&mut connection)?;
+    }
+
+    invocation
+        .reply(embed::build(format!(
+            "Deleted the playlist **{}**",
+            playlist.name
+        )))
+        .await?;
+
+    Ok(())
+}
+
+/// Saves the current track and the queue to the playlist, creating it when it doesn't exist yet
+pub async fn save(invocation: &Invocation<'_>, guild: bool, name: Option<&str>) -> CommandResult {
+    let ctx = invocation.ctx;
+    let owner = owner(invocation, guild);
+    let Some(name) = check_name(invocation, name).await? else {
+        return Ok(());
+    };
+
+    if !require_editor(invocation, owner).await? {
+        return Ok(());
+    }
+
+    let tracks = {
+        let manager = music::get_manager(ctx, invocation.guild_id()).await;
+        let music = manager.lock().await;
+        music
+            .now_playing()
+            .into_iter()
+            .chain(music.tracks())
+            .take(MAX_PLAYLIST_SIZE)
+            .map(to_saved)
+            .collect::<Vec<_>>()
+    };
+
+    if tracks.is_empty() {
+        invocation
+            .error(embed::error("The queue is empty!"))
+            .await?;
+        return Ok(());
+    }
+
+    let count = tracks.len();
+    {
+        let database = database(ctx).await;
+        let mut connection = database.lock().await;
+        let playlist = match Playlist::find(&mut connection, owner, name)? {
+            Some(playlist) => playlist,
+            None => Playlist::create(&mut connection, owner, name)?,
+        };
+        playlist.set_tracks(&mut connection, tracks)?;
+    }
+
+    invocation
+        .reply(embed::build(format!(
+            "Saved **{}** tracks of the queue to **{}**",
+            count, name
+        )))
+        .await?;
+
+    Ok(())
+}
+
+pub async fn playlistrole(invocation: &Invocation<'_>, role: Option<&str>) -> CommandResult {
+    let ctx = invocation.ctx;
+    let mut settings = settings::get_settings(ctx, invocation.guild_id()).await?;
+
+    let Some(role) = role else {
+        let role = match settings.playlist_role() {
+            Some(role) => format!("Guild playlists can be edited by {}", role.mention()),
+            None => "Guild playlists can only be edited by managers".to_string(),
+        };
+
+        invocation
+            .reply(embed::build(format!(
+                "{}\nUsage: `playlistrole <role|none>`",
+                role
+            )))
+            .await?;
+
+        return Ok(());
+    };
+
+    let member = invocation.member().await?;
+    if !roles::has_permission(ctx, &member, Permissions::MANAGE_GUILD).await {
+        invocation
+            .error(embed::error(
+                "You need the Manage Server permission to change the playlist role!",
+            ))
+            .await?;
+
+        return Ok(());
+    }
+
+    let role = match role.trim() {
+        "none" | "off" => None,
+        role => match role.parse::<RoleId>() {
+            Ok(role) => Some(role),
+            Err(_) => {
+                invocation
+                    .error(embed::error(
+                        "Mention the role or use its id to set the playlist role!",
+                    ))
+                    .await?;
+
+                return Ok(());
+            }
+        },
+    };
+
+    settings.set_playlist_role(role);
+    settings::save_settings(ctx, &settings).await?;
+
+    let embed = match role {
+        Some(role) => embed::build(format!(
+            "Guild playlists can now be edited by {}",
+            role.mention()
+        )),
+        None => embed::build("Removed the playlist role, only managers can edit guild playlists"),
+    };
+
+    invocation.reply(embed).await?;
+    Ok(())
+}
+
+/// Who the playlist belongs to, the guild's playlists are used over the author's when asked for
+fn owner(invocation: &Invocation<'_>, guild: bool) -> Owner {
+    if guild {
+        Owner::Guild(invocation.guild_id())
+    } else {
+        Owner::User(invocation.author().id)
+    }
+}
+
+/// Checks the name of a playlist, telling the author when it's missing or too long
+async fn check_name<'a>(
+    invocation: &Invocation<'_>,
+    name: Option<&'a str>,
+) -> Result<Option<&'a str>> {
+    let name = name.map(str::trim).unwrap_or_default();
+
+    let error = if name.is_empty() {
+        "You need to provide the name of the playlist!".to_string()
+    } else if name.chars().count() > MAX_NAME_LENGTH {
+        format!(
+            "The name of a playlist can't be longer than {} characters!",
+            MAX_NAME_LENGTH
+        )
+    } else {
+        return Ok(Some(name));
+    };
+
+    invocation.error(embed::error(error)).await?;
+    Ok(None)
+}
+
+/// Finds a playlist of the owner that the author may edit, telling them when they can't
+async fn find_editable(
+    invocation: &Invocation<'_>,
+    owner: Owner,
+    name: Option<&str>,
+) -> Result<Option<Playlist>> {
+    let Some(name) = check_name(invocation, name).await? else {
+        return Ok(None);
+    };
+
+    if !require_editor(invocation, owner).await? {
+        return Ok(None);
+    }
+
+    let database = database(invocation.ctx).await;
+    let playlist = {
+        let mut connection = database.lock().await;
+        Playlist::find(&mut connection, owner, name)?
+    };
+
+    if playlist.is_none() {
+        not_found(invocation, name).await?;
+    }
+
+    Ok(playlist)
+}
+
+/// Finds a playlist to listen to, the author's own playlists are preferred over the guild's
+async fn find_playable(
+    invocation: &Invocation<'_>,
+    owner: Owner,
+    name: Option<&str>,
+) -> Result<Option<Playlist>> {
+    let Some(name) = check_name(invocation, name).await? else {
+        return Ok(None);
+    };
+
+    let database = database(invocation.ctx).await;
+    let playlist = {
+        let mut connection = database.lock().await;
+        match owner {
+            Owner::User(user) => {
+                Playlist::find_playable(&mut connection, user, Some(invocation.guild_id()), name)?
+            }
+            owner => Playlist::find(&mut connection, owner, name)?,
+        }
+    };
+
+    if playlist.is_none() {
+        not_found(invocation, name).await?;
+    }
+
+    Ok(playlist)
+}
+
+async fn not_found(invocation: &Invocation<'_>, name: &str) -> Result<()> {
+    invocation
+        .error(embed::error(format!(
+            "There is no playlist called **{}**!",
+            name
+        )))
+        .await
+}
+
+/// Check if the author may edit the owner's playlists. Everyone can edit their own playlists, guild
+/// playlists need the playlist role or the Manage Server permission.
+async fn require_editor(invocation: &Invocation<'_>, owner: Owner) -> Result<bool> {
+    let Owner::Guild(guild) = owner else {
+        return Ok(true);
+    };
+
+    let ctx = invocation.ctx;
+    let role = settings::get_settings(ctx, guild).await?.playlist_role();
+    let member = invocation.member().await?;
+    if role.is_some_and(|role| member.roles.contains(&role))
+        || roles::has_permission(ctx, &member, Permissions::MANAGE_GUILD).await
+    {
+        return Ok(true);
+    }
+
+    let error = match role {
+        Some(role) => format!(
+            "You need the {} role to edit the guild's playlists!",
+            role.mention()
+        ),
+        None => "You need the Manage Server permission to edit the guild's playlists!".to_string(),
+    };
+
+    invocation.error(embed::error(error)).await?;
+    Ok(false)
+}
+
+/// How the owner is written in front of a playlist name in commands
+fn scope_prefix(owner: Owner) -> &'static str {
+    match owner {
+        Owner::User(_) => "",
+        Owner::Guild(_) => "guild ",
+    }
+}
+
+fn to_saved(track: &Track) -> PlaylistTrack {
+    PlaylistTrack {
+        playlist_id: 0,
+        track_index: 0,
+        source: track.source.id(),
+        title: track.title.clone(),
+        author: track.author.clone(),
+        duration: track.duration.map(|duration| duration.as_secs() as i32),
+        url: track.url.clone(),
+        thumbnail: track.thumbnail.clone(),
+    }
+}
+
+/// The user's playlist with the name, or the guild's, along with its tracks requested by the user.
+/// Used to play a playlist through `play playlist:<name>`.
+pub async fn find_with_tracks(
+    ctx: &Context,
+    guild: GuildId,
+    user: UserId,
+    name: &str,
+) -> Result<Option<(Playlist, Vec<Track>)>> {
+    let playlist = {
+        let database = database(ctx).await;
+        let mut connection = database.lock().await;
+        Playlist::find_playable(&mut connection, user, Some(guild), name)?
+    };
+
+    let Some(playlist) = playlist else {
+        return Ok(None);
+    };
+
+    let tracks = load_tracks(ctx, &playlist, user).await?;
+    Ok(Some((playlist, tracks)))
+}
+
+/// The tracks of the playlist that can still be played, requested by the user
+async fn load_tracks(ctx: &Context, playlist: &Playlist, user: UserId) -> Result<Vec<Track>> {
+    let saved = {
+        let database = database(ctx).await;
+        let mut connection = database.lock().await;
+        playlist.tracks(&mut connection)?
+    };
+
+    let sources = sources(ctx).await;
+    Ok(saved
+        .iter()
+        .filter_map(|track| restore(&sources, track))
+        .map(|track| track.requested_by(user))
+        .collect())
+}
+
+fn restore(sources: &SourceRegistry, saved: &PlaylistTrack) -> Option<Track> {
+    let metadata = Metadata {
+        title: saved.title.clone(),
+        author: saved.author.clone(),
+        duration: saved
+            .duration
+            .map(|seconds| Duration::from_secs(seconds as u64)),
+        thumbnail: saved.thumbnail.clone(),
+    };
+
+    sources.restore(&saved.source, &metadata)
+}
+
+async fn database(ctx: &Context) -> Arc<Mutex<SqliteConnection>> {
+    let typemap = ctx.data.read().await;
+    typemap
+        .get::<DatabaseKey>()
+        .expect("Expected DatabaseKey in TypeMap.")
+        .clone()
+}
+
+async fn sources(ctx: &Context) -> Arc<SourceRegistry> {
+    let typemap = ctx.data.read().await;
+    typemap
+        .get::<SourceKey>()
+        .expect("Expected SourceKey in TypeMap.")
+        .clone()
+}
@@ -0,0 +1,325 @@
+//! Prefix commands, these read their arguments from the message and run the shared commands
+
+use serenity::{
+    all::Message,
+    client::Context,
+    framework::standard::{
+        macros::{command, group, hook},
+        Args, CommandResult,
+    },
+};
+
+use crate::{discord::invocation::Invocation, helper::settings, models::settings::DEFAULT_PREFIX};
+
+use super::{general, music, playlist};
+
+#[group]
+#[commands(ping, roles, config)]
+struct General;
+
+#[group]
+#[commands(
+    play,
+    countdown,
+    skip,
+    queue,
+    remove,
+    move_track,
+    clear,
+    shuffle,
+    fair,
+    previous,
+    loop_mode,
+    autoplay,
+    nowplaying,
+    pause,
+    resume,
+    seek,
+    volume,
+    filter,
+    crossfade,
+    stop,
+    forceskip,
+    djrole,
+    always_connected,
+    normalize
+)]
+struct Music;
+
+#[group]
+#[commands(playlist, playlistrole)]
+struct Playlists;
+
+/// The prefix the guild chose, falling back to the default one when the settings can't be loaded
+#[hook]
+pub async fn guild_prefix(ctx: &Context, msg: &Message) -> Option<String> {
+    let Some(guild) = msg.guild_id else {
+        return Some(DEFAULT_PREFIX.to_string());
+    };
+
+    match settings::get_settings(ctx, guild).await {
+        Ok(settings) => Some(settings.prefix().to_string()),
+        Err(why) => {
+            println!("Failed to load the prefix: {:?}", why);
+            Some(DEFAULT_PREFIX.to_string())
+        }
+    }
+}
+
+#[command]
+async fn ping(ctx: &Context, msg: &Message) -> CommandResult {
+    general::ping(&Invocation::message(ctx, msg)).await
+}
+
+#[command]
+async fn roles(ctx: &Context, msg: &Message) -> CommandResult {
+    general::roles(&Invocation::message(ctx, msg)).await
+}
+
+#[command]
+#[aliases(settings)]
+async fn config(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let setting = args.single::<String>().ok();
+    let value = (!args.is_empty()).then(|| args.rest());
+    general::config(&Invocation::message(ctx, msg), setting.as_deref(), value).await
+}
+
+#[command]
+async fn countdown(ctx: &Context, msg: &Message) -> CommandResult {
+    music::countdown(&Invocation::message(ctx, msg)).await
+}
+
+#[command]
+async fn play(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let query = args.raw().collect::<Vec<&str>>().join(" ");
+    music::play(&Invocation::message(ctx, msg), &query).await
+}
+
+#[command]
+async fn skip(ctx: &Context, msg: &Message) -> CommandResult {
+    music::skip(&Invocation::message(ctx, msg)).await
+}
+
+#[command]
+#[aliases(fs)]
+async fn forceskip(ctx: &Context, msg: &Message) -> CommandResult {
+    music::forceskip(&Invocation::message(ctx, msg)).await
+}
+
+#[command]
+#[aliases(q)]
+async fn queue(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    music::queue(&Invocation::message(ctx, msg), args.single().ok()).await
+}
+
+#[command]
+#[aliases(rm)]
+async fn remove(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    music::remove(&Invocation::message(ctx, msg), args.single().ok()).await
+}
+
+#[command("move")]
+#[aliases(mv)]
+async fn move_track(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let positions = match (args.single(), args.single()) {
+        (Ok(from), Ok(to)) => Some((from, to)),
+        _ => None,
+    };
+
+    music::move_track(&Invocation::message(ctx, msg), positions).await
+}
+
+#[command]
+async fn clear(ctx: &Context, msg: &Message) -> CommandResult {
+    music::clear(&Invocation::message(ctx, msg)).await
+}
+
+#[command]
+async fn shuffle(ctx: &Context, msg: &Message) -> CommandResult {
+    music::shuffle(&Invocation::message(ctx, msg)).await
+}
+
+#[command]
+#[aliases(fairqueue)]
+async fn fair(ctx: &Context, msg: &Message) -> CommandResult {
+    music::fair(&Invocation::message(ctx, msg)).await
+}
+
+#[command]
+#[aliases(prev, back)]
+async fn previous(ctx: &Context, msg: &Message) -> CommandResult {
+    music::previous(&Invocation::message(ctx, msg)).await
+}
+
+#[command("loop")]
+async fn loop_mode(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let mode = args.single::<String>().ok();
+    music::loop_mode(&Invocation::message(ctx, msg), mode.as_deref()).await
+}
+
+#[command]
+#[aliases(radio)]
+async fn autoplay(ctx: &Context, msg: &Message) -> CommandResult {
+    music::autoplay(&Invocation::message(ctx, msg)).await
+}
+
+#[command]
+#[aliases(np)]
+async fn nowplaying(ctx: &Context, msg: &Message) -> CommandResult {
+    music::nowplaying(&Invocation::message(ctx, msg)).await
+}
+
+#[command]
+async fn pause(ctx: &Context, msg: &Message) -> CommandResult {
+    music::pause(&Invocation::message(ctx, msg)).await
+}
+
+#[command]
+#[aliases(unpause)]
+async fn resume(ctx: &Context, msg: &Message) -> CommandResult {
+    music::resume(&Invocation::message(ctx, msg)).await
+}
+
+#[command]
+async fn seek(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let timestamp = args.single::<String>().ok();
+    music::seek(&Invocation::message(ctx, msg), timestamp.as_deref()).await
+}
+
+#[command]
+#[aliases(vol)]
+async fn volume(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    music::volume(&Invocation::message(ctx, msg), args.single().ok()).await
+}
+
+#[command]
+#[aliases(filters)]
+async fn filter(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    music::filter(&Invocation::message(ctx, msg), args).await
+}
+
+#[command]
+async fn crossfade(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    music::crossfade(&Invocation::message(ctx, msg), args.single().ok()).await
+}
+
+#[command]
+async fn stop(ctx: &Context, msg: &Message) -> CommandResult {
+    music::stop(&Invocation::message(ctx, msg)).await
+}
+
+#[command]
+async fn djrole(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let role = (!args.is_empty()).then(|| args.rest());
+    music::djrole(&Invocation::message(ctx, msg), role).await
+}
+
+#[command("247")]
+#[aliases("24/7")]
+async fn always_connected(ctx: &Context, msg: &Message) -> CommandResult {
+    music::always_connected(&Invocation::message(ctx, msg)).await
+}
+
+#[command]
+async fn normalize(ctx: &Context, msg: &Message) -> CommandResult {
+    music::normalize(&Invocation::message(ctx, msg)).await
+}
+
+#[command]
+#[aliases(pl)]
+#[sub_commands(
+    playlist_create,
+    playlist_add,
+    playlist_remove,
+    playlist_show,
+    playlist_play,
+    playlist_delete,
+    playlist_save
+)]
+async fn playlist(ctx: &Context, msg: &Message) -> CommandResult {
+    playlist::usage(&Invocation::message(ctx, msg)).await
+}
+
+#[command("create")]
+async fn playlist_create(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild = parse_guild(&mut args);
+    let name = args.single_quoted::<String>().ok();
+    playlist::create(&Invocation::message(ctx, msg), guild, name.as_deref()).await
+}
+
+#[command("add")]
+async fn playlist_add(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild = parse_guild(&mut args);
+    let name = args.single_quoted::<String>().ok();
+    let query = args.rest();
+    playlist::add(
+        &Invocation::message(ctx, msg),
+        guild,
+        name.as_deref(),
+        query,
+    )
+    .await
+}
+
+#[command("remove")]
+#[aliases(rm)]
+async fn playlist_remove(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild = parse_guild(&mut args);
+    let name = args.single_quoted::<String>().ok();
+    let position = args.single().ok();
+    playlist::remove(
+        &Invocation::message(ctx, msg),
+        guild,
+        name.as_deref(),
+        position,
+    )
+    .await
+}
+
+#[command("show")]
+#[aliases(list)]
+async fn playlist_show(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild = parse_guild(&mut args);
+    let name = args.single_quoted::<String>().ok();
+    let page = args.single().ok();
+    playlist::show(&Invocation::message(ctx, msg), guild, name.as_deref(), page).await
+}
+
+#[command("play")]
+async fn playlist_play(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild = parse_guild(&mut args);
+    let name = args.single_quoted::<String>().ok();
+    let option = args.single::<String>().ok();
+    let invocation = Invocation::message(ctx, msg);
+    playlist::play(&invocation, guild, name.as_deref(), option.as_deref()).await
+}
+
+#[command("delete")]
+async fn playlist_delete(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild = parse_guild(&mut args);
+    let name = args.single_quoted::<String>().ok();
+    playlist::delete(&Invocation::message(ctx, msg), guild, name.as_deref()).await
+}
+
+#[command("save")]
+async fn playlist_save(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild = parse_guild(&mut args);
+    let name = args.single_quoted::<String>().ok();
+    playlist::save(&Invocation::message(ctx, msg), guild, name.as_deref()).await
+}
+
+#[command]
+async fn playlistrole(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let role = (!args.is_empty()).then(|| args.rest());
+    playlist::playlistrole(&Invocation::message(ctx, msg), role).await
+}
+
+/// Reads a leading `guild`, which picks the guild's playlists over the author's
+fn parse_guild(args: &mut Args) -> bool {
+    let guild = matches!(args.current(), Some("guild" | "server"));
+    if guild {
+        args.advance();
+    }
+
+    guild
+}
@@ -0,0 +1,528 @@
+//! Slash commands, these read their arguments from the typed options and run the shared commands
+
+use anyhow::Result;
+use serenity::{
+    all::{
+        Command, CommandInteraction, CommandOptionType, GuildId, ResolvedOption, ResolvedValue,
+        RoleId,
+    },
+    builder::{CreateCommand, CreateCommandOption},
+    client::Context,
+    framework::standard::{Args, Delimiter},
+};
+
+use crate::{
+    discord::invocation::Invocation, guilds::music::filter, helper::embed,
+    models::settings::Setting, ConfigKey,
+};
+
+use super::{autocomplete, general, music, playlist};
+
+/// Registers the slash commands in the configured guilds, or globally when there are none.
+/// Guild commands show up right away, global commands can take a while to reach every guild.
+pub async fn register(ctx: &Context) -> Result<()> {
+    let guilds = {
+        let typemap = ctx.data.read().await;
+        typemap
+            .get::<ConfigKey>()
+            .expect("Expected ConfigKey in TypeMap.")
+            .commands
+            .guilds
+            .clone()
+    };
+
+    if guilds.is_empty() {
+        Command::set_global_commands(&ctx.http, commands()).await?;
+    } else {
+        for guild in guilds {
+            GuildId::new(guild)
+                .set_commands(&ctx.http, commands())
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+fn commands() -> Vec<CreateCommand> {
+    vec![
+        command("ping", "Shows how long it takes the bot to respond"),
+        command("roles", "Lists the roles of the server"),
+        command("config", "Shows or changes the settings of the server")
+            .add_option(Setting::ALL.iter().fold(
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "setting",
+                    "The setting to show or change",
+                ),
+                |option, setting| option.add_string_choice(setting.name(), setting.name()),
+            ))
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::String,
+                "value",
+                "The new value, reset changes it back to the default",
+            )),
+        command("countdown", "Counts down"),
+        command("play", "Plays a track or playlist, or searches for one").add_option(
+            CreateCommandOption::new(
+                CommandOptionType::String,
+                "query",
+                "A link or what to search for",
+            )
+            .required(true)
+            .set_autocomplete(true),
+        ),
+        command("skip", "Skips the current track, or votes to skip it"),
+        command("forceskip", "Skips the current track without voting"),
+        command("queue", "Shows the upcoming tracks").add_option(
+            CreateCommandOption::new(CommandOptionType::Integer, "page", "The page to show")
+                .min_int_value(1),
+        ),
+        command("remove", "Removes a track from the queue").add_option(
+            CreateCommandOption::new(
+                CommandOptionType::Integer,
+                "position",
+                "The position of the track in the queue",
+            )
+            .min_int_value(1)
+            .required(true),
+        ),
+        command("move", "Moves a track to another position in the queue")
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::Integer,
+                    "from",
+                    "The position of the track in the queue",
+                )
+                .min_int_value(1)
+                .required(true),
+            )
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::Integer,
+                    "to",
+                    "The position to move the track to",
+                )
+                .min_int_value(1)
+                .required(true),
+            ),
+        command("clear", "Removes every track from the queue"),
+        command("shuffle", "Puts the queue in a random order"),
+        command("fair", "Toggles letting everyone's tracks play in turns"),
+        command("previous", "Plays the previous track again"),
+        command("loop", "Shows or changes what is looped").add_option(
+            CreateCommandOption::new(CommandOptionType::String, "mode", "What to loop")
+                .add_string_choice("Nothing", "none")
+                .add_string_choice("The current track", "track")
+                .add_string_choice("The whole queue", "queue"),
+        ),
+        command(
+            "autoplay",
+            "Toggles playing related tracks when the queue runs out",
+        ),
+        command("nowplaying", "Shows the current track"),
+        command("pause", "Pauses the current track"),
+        command("resume", "Resumes the current track"),
+        command("seek", "Jumps to a position in the current track").add_option(
+            CreateCommandOption::new(
+                CommandOptionType::String,
+                "timestamp",
+                "Where to jump to, such as 1:30",
+            )
+            .required(true),
+        ),
+        command("volume", "Shows or changes the volume").add_option(
+            CreateCommandOption::new(
+                CommandOptionType::Integer,
+                "volume",
+                "The volume in percent",
+            )
+            .min_int_value(0)
+            .max_int_value(200),
+        ),
+        command("filter", "Shows or changes the audio filters")
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "filter",
+                    "The filter to change",
+                )
+                .add_string_choice("Bass boost", "bassboost")
+                .add_string_choice("Nightcore", "nightcore")
+                .add_string_choice("Vaporwave", "vaporwave")
+                .add_string_choice("Speed", "speed")
+                .add_string_choice("Equalizer", "eq")
+                .add_string_choice("Reset the equalizer", "eq reset")
+                .add_string_choice("Reset every filter", "reset"),
+            )
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::Number,
+                "value",
+                "The bass boost or equalizer gain in dB, or the speed",
+            ))
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::Integer,
+                    "band",
+                    "The equalizer band to change",
+                )
+                .min_int_value(1)
+                .max_int_value(filter::EQ_BANDS.len() as u64),
+            ),
+        command("crossfade", "Shows or changes how long tracks overlap").add_option(
+            CreateCommandOption::new(
+                CommandOptionType::Integer,
+                "seconds",
+                "How many seconds tracks overlap, 0 turns it off",
+            )
+            .min_int_value(0)
+            .max_int_value(music::MAX_CROSSFADE),
+        ),
+        command("stop", "Stops playing and clears the queue"),
+        command("djrole", "Shows or changes the DJ role")
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::Role,
+                "role",
+                "The role that may use every music command",
+            ))
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::Boolean,
+                "remove",
+                "Remove the DJ role, so everyone may use every music command",
+            )),
+        command("247", "Toggles staying in the voice channel when idle"),
+        command(
+            "normalize",
+            "Toggles playing every track at about the same loudness",
+        ),
+        command("playlist", "Manages and plays saved playlists")
+            .add_option(playlist_subcommand(
+                "create",
+                "Creates an empty playlist",
+                // There is nothing to suggest for a new name
+                vec![playlist_name().set_autocomplete(false).required(true)],
+            ))
+            .add_option(playlist_subcommand(
+                "add",
+                "Adds a track or playlist to a playlist",
+                vec![
+                    playlist_name().required(true),
+                    CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "query",
+                        "A link or what to search for",
+                    )
+                    .required(true),
+                ],
+            ))
+            .add_option(playlist_subcommand(
+                "remove",
+                "Removes a track from a playlist",
+                vec![
+                    playlist_name().required(true),
+                    CreateCommandOption::new(
+                        CommandOptionType::Integer,
+                        "position",
+                        "The position of the track in the playlist",
+                    )
+                    .min_int_value(1)
+                    .required(true),
+                ],
+            ))
+            .add_option(playlist_subcommand(
+                "show",
+                "Shows the tracks of a playlist, or every playlist",
+                vec![
+                    playlist_name(),
+                    CreateCommandOption::new(
+                        CommandOptionType::Integer,
+                        "page",
+                        "The page to show",
+                    )
+                    .min_int_value(1),
+                ],
+            ))
+            .add_option(playlist_subcommand(
+                "play",
+                "Queues the tracks of a playlist",
+                vec![
+                    playlist_name().required(true),
+                    CreateCommandOption::new(
+                        CommandOptionType::Boolean,
+                        "shuffle",
+                        "Queue the tracks in a random order",
+                    ),
+                ],
+            ))
+            .add_option(playlist_subcommand(
+                "delete",
+                "Deletes a playlist",
+                vec![playlist_name().required(true)],
+            ))
+            .add_option(playlist_subcommand(
+                "save",
+                "Saves the queue to a playlist, creating it when needed",
+                vec![playlist_name().required(true)],
+            )),
+        command(
+            "playlistrole",
+            "Shows or changes who can edit guild playlists",
+        )
+        .add_option(CreateCommandOption::new(
+            CommandOptionType::Role,
+            "role",
+            "The role that may edit guild playlists",
+        ))
+        .add_option(CreateCommandOption::new(
+            CommandOptionType::Boolean,
+            "remove",
+            "Remove the playlist role, so only managers may edit guild playlists",
+        )),
+    ]
+}
+
+/// A subcommand of the playlist command, each of them can use the guild's playlists.
+/// Discord wants the required options first, so the guild option comes last.
+fn playlist_subcommand(
+    name: &str,
+    description: &str,
+    options: Vec<CreateCommandOption>,
+) -> CreateCommandOption {
+    options
+        .into_iter()
+        .fold(
+            CreateCommandOption::new(CommandOptionType::SubCommand, name, description),
+            |subcommand, option| subcommand.add_sub_option(option),
+        )
+        .add_sub_option(CreateCommandOption::new(
+            CommandOptionType::Boolean,
+            "guild",
+            "Use the guild's playlists instead of your own",
+        ))
+}
+
+fn playlist_name() -> CreateCommandOption {
+    CreateCommandOption::new(
+        CommandOptionType::String,
+        "name",
+        "The name of the playlist",
+    )
+    .max_length(playlist::MAX_NAME_LENGTH as u16)
+    .set_autocomplete(true)
+}
+
+fn command(name: &str, description: &str) -> CreateCommand {
+    CreateCommand::new(name)
+        .description(description)
+        .dm_permission(false)
+}
+
+/// Runs the slash command, letting the user know when it failed
+pub async fn run(ctx: &Context, interaction: &CommandInteraction) {
+    let invocation = Invocation::interaction(ctx, interaction);
+    let options = Options(interaction.data.options());
+
+    let result = match interaction.data.name.as_str() {
+        "ping" => general::ping(&invocation).await,
+        "roles" => general::roles(&invocation).await,
+        "config" => {
+            let (setting, value) = (options.string("setting"), options.string("value"));
+            general::config(&invocation, setting, value).await
+        }
+        "countdown" => music::countdown(&invocation).await,
+        "play" => music::play(&invocation, options.string("query").unwrap_or_default()).await,
+        "skip" => music::skip(&invocation).await,
+        "forceskip" => music::forceskip(&invocation).await,
+        "queue" => music::queue(&invocation, options.position("page")).await,
+        "remove" => music::remove(&invocation, options.position("position")).await,
+        "move" => {
+            let positions = options.position("from").zip(options.position("to"));
+            music::move_track(&invocation, positions).await
+        }
+        "clear" => music::clear(&invocation).await,
+        "shuffle" => music::shuffle(&invocation).await,
+        "fair" => music::fair(&invocation).await,
+        "previous" => music::previous(&invocation).await,
+        "loop" => music::loop_mode(&invocation, options.string("mode")).await,
+        "autoplay" => music::autoplay(&invocation).await,
+        "nowplaying" => music::nowplaying(&invocation).await,
+        "pause" => music::pause(&invocation).await,
+        "resume" => music::resume(&invocation).await,
+        "seek" => music::seek(&invocation, options.string("timestamp")).await,
+        "volume" => {
+            let volume = options.integer("volume").map(|volume| volume as u32);
+            music::volume(&invocation, volume).await
+        }
+        "filter" => {
+            // The filter command reads its values the same way as the prefix command does
+            let text = [
+                options.string("filter").map(str::to_string),
+                options.integer("band").map(|band| band.to_string()),
+                options.number("value").map(|value| value.to_string()),
+            ]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join(" ");
+
+            music::filter(&invocation, Args::new(&text, &[Delimiter::Single(' ')])).await
+        }
+        "crossfade" => {
+            let seconds = options.integer("seconds").map(|seconds| seconds as u64);
+            music::crossfade(&invocation, seconds).await
+        }
+        "stop" => music::stop(&invocation).await,
+        "djrole" => {
+            let role = match (options.role("role"), options.boolean("remove")) {
+                (_, Some(true)) => Some("none".to_string()),
+                (Some(role), _) => Some(role.to_string()),
+                _ => None,
+            };
+
+            music::djrole(&invocation, role.as_deref()).await
+        }
+        "247" => music::always_connected(&invocation).await,
+        "normalize" => music::normalize(&invocation).await,
+        "playlist" => {
+            let Some((subcommand, options)) = options.subcommand() else {
+                return;
+            };
+
+            let guild = options.boolean("guild").unwrap_or(false);
+            let name = options.string("name");
+            match subcommand {
+                "create" => playlist::create(&invocation, guild, name).await,
+                "add" => {
+                    let query = options.string("query").unwrap_or_default();
+                    playlist::add(&invocation, guild, name, query).await
+                }
+                "remove" => {
+                    let position = options.position("position");
+                    playlist::remove(&invocation, guild, name, position).await
+                }
+                "show" => playlist::show(&invocation, guild, name, options.position("page")).await,
+                "play" => {
+                    let option = options
+                        .boolean("shuffle")
+                        .unwrap_or(false)
+                        .then_some("shuffle");
+                    playlist::play(&invocation, guild, name, option).await
+                }
+                "delete" => playlist::delete(&invocation, guild, name).await,
+                "save" => playlist::save(&invocation, guild, name).await,
+                _ => playlist::usage(&invocation).await,
+            }
+        }
+        "playlistrole" => {
+            let role = match (options.role("role"), options.boolean("remove")) {
+                (_, Some(true)) => Some("none".to_string()),
+                (Some(role), _) => Some(role.to_string()),
+                _ => None,
+            };
+
+            playlist::playlistrole(&invocation, role.as_deref()).await
+        }
+        name => {
+            println!("Received an unknown slash command: {}", name);
+            return;
+        }
+    };
+
+    if let Err(why) = result {
+        println!(
+            "Failed to run slash command {}: {:?}",
+            interaction.data.name, why
+        );
+
+        let _ = invocation
+            .error(embed::error(
+                "Something went wrong while running the command!",
+            ))
+            .await;
+    }
+}
+
+/// Suggests values for the option the user is typing in
+pub async fn autocomplete(ctx: &Context, interaction: &CommandInteraction) {
+    let result = match interaction.data.name.as_str() {
+        "play" => autocomplete::play(ctx, interaction).await,
+        "playlist" => {
+            let Some((subcommand, options)) = Options(interaction.data.options()).subcommand()
+            else {
+                return;
+            };
+
+            let guild = options.boolean("guild").unwrap_or(false);
+            autocomplete::playlist(ctx, interaction, subcommand, guild).await
+        }
+        _ => return,
+    };
+
+    if let Err(why) = result {
+        println!(
+            "Failed to suggest values for slash command {}: {:?}",
+            interaction.data.name, why
+        );
+    }
+}
+
+/// The options a slash command was used with
+struct Options<'a>(Vec<ResolvedOption<'a>>);
+
+impl<'a> Options<'a> {
+    /// The subcommand that was used along with its options, such as `create` of the playlist command
+    fn subcommand(self) -> Option<(&'a str, Options<'a>)> {
+        let option = self.0.into_iter().next()?;
+        match option.value {
+            ResolvedValue::SubCommand(options) => Some((option.name, Options(options))),
+            _ => None,
+        }
+    }
+
+    fn get(&self, name: &str) -> Option<&ResolvedValue<'a>> {
+        self.0
+            .iter()
+            .find(|option| option.name == name)
+            .map(|option| &option.value)
+    }
+
+    fn string(&self, name: &str) -> Option<&'a str> {
+        match self.get(name)? {
+            ResolvedValue::String(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    fn integer(&self, name: &str) -> Option<i64> {
+        match self.get(name)? {
+            ResolvedValue::Integer(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// A 1-based position, such as in the queue
+    fn position(&self, name: &str) -> Option<usize> {
+        self.integer(name).map(|position| position.max(0) as usize)
+    }
+
+    fn number(&self, name: &str) -> Option<f64> {
+        match self.get(name)? {
+            ResolvedValue::Number(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    fn boolean(&self, name: &str) -> Option<bool> {
+        match self.get(name)? {
+            ResolvedValue::Boolean(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    fn role(&self, name: &str) -> Option<RoleId> {
+        match self.get(name)? {
+            ResolvedValue::Role(role) => Some(role.id),
+            _ => None,
+        }
+    }
+}
@@ -1,13 +1,106 @@
-use std::fs;
+use std::{fs, path::PathBuf};
 
 use serde::Deserialize;
 
 #[derive(Deserialize)]
 pub struct Config {
     pub token: String,
+    #[serde(default)]
+    pub music: MusicSettings,
+    #[serde(default)]
+    pub commands: CommandSettings,
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct CommandSettings {
+    /// Guilds to register the slash commands in, they are registered globally if empty
+    pub guilds: Vec<u64>,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct MusicSettings {
+    /// Always play the first search result instead of letting the user pick one
+    pub auto_pick: bool,
+    /// How many search results are shown when picking a track
+    pub search_results: usize,
+    /// How many seconds the user has to pick a track
+    pub pick_timeout: u64,
+    /// Skipping needs votes from the listeners, unless it's skipped by a DJ or the requester
+    pub vote_skip: bool,
+    /// Percentage of the listeners that need to vote to skip
+    pub skip_threshold: u8,
+    /// Minutes to wait with nothing playing before leaving the voice channel
+    pub idle_timeout: u64,
+    /// What to do when every listener left the voice channel
+    pub empty_action: EmptyAction,
+    /// How many tracks are imported from a single playlist at most
+    pub playlist_limit: usize,
+    /// Directory with audio files that can be played through `local:<query>`
+    pub library: Option<PathBuf>,
+    /// Directory where the audio of recently played tracks is kept, caching is disabled if unset
+    pub cache: Option<PathBuf>,
+    /// How large the cache may grow, in megabytes
+    pub cache_size: u64,
+}
+
+impl Default for MusicSettings {
+    fn default() -> Self {
+        MusicSettings {
+            auto_pick: false,
+            search_results: 5,
+            pick_timeout: 30,
+            vote_skip: false,
+            skip_threshold: 50,
+            idle_timeout: 5,
+            empty_action: EmptyAction::Leave,
+            playlist_limit: 100,
+            library: None,
+            cache: None,
+            cache_size: 1024,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum EmptyAction {
+    /// Leave the voice channel shortly after
+    Leave,
+    /// Pause until someone joins again, leaving once the idle timeout passes
+    Pause,
+}
+
+impl MusicSettings {
+    /// Makes sure every value can be used, such as a skip threshold that votes are able to reach
+    fn validate(&self) -> Result<(), String> {
+        // The picker is a select menu, which needs at least one option and allows at most 25
+        if !(1..=25).contains(&self.search_results) {
+            return Err(format!(
+                "search_results must be between 1 and 25, not {}",
+                self.search_results
+            ));
+        }
+
+        if !(1..=100).contains(&self.skip_threshold) {
+            return Err(format!(
+                "skip_threshold must be between 1 and 100, not {}",
+                self.skip_threshold
+            ));
+        }
+
+        Ok(())
+    }
 }
 
 pub fn get_config() -> Config {
     let data = fs::read_to_string("config.toml").expect("Unable to read config file");
-    toml::from_str(&data).unwrap()
+    let config: Config = toml::from_str(&data).unwrap();
+
+    if let Err(why) = config.music.validate() {
+        panic!("Invalid music settings in config file: {}", why);
+    }
+
+    config
 }
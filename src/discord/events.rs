@@ -0,0 +1,49 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use serenity::{
+    all::{Interaction, Ready, VoiceState},
+    async_trait,
+    client::{Context, EventHandler},
+};
+
+use crate::{
+    command::{component, slash},
+    guilds::music::{idle, persist},
+};
+
+#[derive(Default)]
+pub struct Events {
+    /// Ready fires again after reconnecting, but saved queues should only be restored once
+    /// and the slash commands only registered once
+    restored: AtomicBool,
+}
+
+#[async_trait]
+impl EventHandler for Events {
+    async fn ready(&self, ctx: Context, _ready: Ready) {
+        if self.restored.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        if let Err(why) = persist::restore_all(&ctx).await {
+            println!("Failed to restore saved queues: {:?}", why);
+        }
+
+        if let Err(why) = slash::register(&ctx).await {
+            println!("Failed to register slash commands: {:?}", why);
+        }
+    }
+
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        match interaction {
+            Interaction::Command(command) => slash::run(&ctx, &command).await,
+            Interaction::Autocomplete(command) => slash::autocomplete(&ctx, &command).await,
+            Interaction::Component(component) => component::run(&ctx, &component).await,
+            _ => {}
+        }
+    }
+
+    async fn voice_state_update(&self, ctx: Context, old: Option<VoiceState>, new: VoiceState) {
+        idle::on_voice_state_update(&ctx, old.as_ref(), &new).await;
+    }
+}
@@ -0,0 +1,254 @@
+//! Lets a command reply the same way whether it was used through a message, a slash command or a button
+
+use anyhow::{anyhow, Result};
+use serenity::{
+    all::{ChannelId, CommandInteraction, ComponentInteraction, GuildId, Member, Message, User},
+    builder::{
+        CreateActionRow, CreateEmbed, CreateInteractionResponse, CreateInteractionResponseFollowup,
+        CreateInteractionResponseMessage, CreateMessage, EditInteractionResponse,
+    },
+    client::Context,
+};
+use tokio::sync::Mutex;
+
+pub struct Invocation<'a> {
+    pub ctx: &'a Context,
+    source: Source<'a>,
+    /// How far an interaction has been responded to
+    response: Mutex<Response>,
+}
+
+enum Source<'a> {
+    Message(&'a Message),
+    Command(&'a CommandInteraction),
+    /// A button, such as on the player
+    Component(&'a ComponentInteraction),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Response {
+    None,
+    /// Discord shows that the bot is thinking until the first reply
+    Deferred,
+    Sent,
+}
+
+/// Runs the same code for either kind of interaction, they are responded to in the same way
+macro_rules! with_interaction {
+    ($source:expr, $interaction:ident => $body:expr) => {
+        match $source {
+            Source::Command($interaction) => $body,
+            Source::Component($interaction) => $body,
+            Source::Message(_) => unreachable!("messages aren't interactions"),
+        }
+    };
+}
+
+impl<'a> Invocation<'a> {
+    pub fn message(ctx: &'a Context, msg: &'a Message) -> Self {
+        Self::new(ctx, Source::Message(msg))
+    }
+
+    pub fn interaction(ctx: &'a Context, interaction: &'a CommandInteraction) -> Self {
+        Self::new(ctx, Source::Command(interaction))
+    }
+
+    pub fn component(ctx: &'a Context, interaction: &'a ComponentInteraction) -> Self {
+        Self::new(ctx, Source::Component(interaction))
+    }
+
+    fn new(ctx: &'a Context, source: Source<'a>) -> Self {
+        Invocation {
+            ctx,
+            source,
+            response: Mutex::new(Response::None),
+        }
+    }
+
+    pub fn author(&self) -> &User {
+        match self.source {
+            Source::Message(msg) => &msg.author,
+            Source::Command(interaction) => &interaction.user,
+            Source::Component(interaction) => &interaction.user,
+        }
+    }
+
+    /// The guild the command was used in, commands can't be used in direct messages
+    pub fn guild_id(&self) -> GuildId {
+        match self.source {
+            Source::Message(msg) => msg.guild_id,
+            Source::Command(interaction) => interaction.guild_id,
+            Source::Component(interaction) => interaction.guild_id,
+        }
+        .expect("Commands are only used in guilds")
+    }
+
+    pub fn channel_id(&self) -> ChannelId {
+        match self.source {
+            Source::Message(msg) => msg.channel_id,
+            Source::Command(interaction) => interaction.channel_id,
+            Source::Component(interaction) => interaction.channel_id,
+        }
+    }
+
+    pub async fn member(&self) -> Result<Member> {
+        let member = match self.source {
+            Source::Message(msg) => return Ok(msg.member(self.ctx).await?),
+            Source::Command(interaction) => interaction.member.as_deref(),
+            Source::Component(interaction) => interaction.member.as_ref(),
+        };
+
+        member
+            .cloned()
+            .ok_or_else(|| anyhow!("The command wasn't used in a guild"))
+    }
+
+    /// Lets the user know the command is being worked on, for commands that may take a while
+    pub async fn defer(&self) -> Result<()> {
+        match self.source {
+            Source::Message(msg) => msg.channel_id.broadcast_typing(&self.ctx.http).await?,
+            Source::Command(interaction) => {
+                let mut response = self.response.lock().await;
+                if *response == Response::None {
+                    interaction.defer(&self.ctx.http).await?;
+                    *response = Response::Deferred;
+                }
+            }
+            // Deferring a button would edit the message it's on, such as the player, so buttons
+            // only run commands that respond right away
+            Source::Component(_) => {}
+        }
+
+        Ok(())
+    }
+
+    /// Makes sure an interaction is responded to, Discord shows that it failed otherwise.
+    /// Nothing is shown when the command didn't reply by itself.
+    pub async fn acknowledge(&self) -> Result<()> {
+        if let Source::Message(_) = self.source {
+            return Ok(());
+        }
+
+        let mut response = self.response.lock().await;
+        if *response == Response::None {
+            with_interaction!(self.source, interaction => {
+                interaction
+                    .create_response(&self.ctx.http, CreateInteractionResponse::Acknowledge)
+                    .await?
+            });
+            *response = Response::Sent;
+        }
+
+        Ok(())
+    }
+
+    /// Replies in the channel. Buttons reply only to the user that pressed them, the player
+    /// already shows everyone what changed.
+    pub async fn reply(&self, embed: CreateEmbed) -> Result<()> {
+        let ephemeral = matches!(self.source, Source::Component(_));
+        self.respond(embed, Vec::new(), ephemeral, false).await?;
+        Ok(())
+    }
+
+    /// Replies with an error, only the user of an interaction gets to see it
+    pub async fn error(&self, embed: CreateEmbed) -> Result<()> {
+        self.respond(embed, Vec::new(), true, false).await?;
+        Ok(())
+    }
+
+    /// Confirms that the command worked when the outcome is already announced in the channel.
+    /// Nothing is sent for messages, while interactions get a reply only the user sees.
+    pub async fn confirm(&self, embed: CreateEmbed) -> Result<()> {
+        if let Source::Message(_) = self.source {
+            return Ok(());
+        }
+
+        self.respond(embed, Vec::new(), true, false).await?;
+        Ok(())
+    }
+
+    /// Replies and returns the message, so it can be edited or listened to later on
+    pub async fn send(
+        &self,
+        embed: CreateEmbed,
+        components: Vec<CreateActionRow>,
+    ) -> Result<Message> {
+        self.respond(embed, components, false, true)
+            .await?
+            .ok_or_else(|| anyhow!("The reply was not sent"))
+    }
+
+    async fn respond(
+        &self,
+        embed: CreateEmbed,
+        components: Vec<CreateActionRow>,
+        ephemeral: bool,
+        fetch: bool,
+    ) -> Result<Option<Message>> {
+        if let Source::Message(msg) = self.source {
+            let message = CreateMessage::new().embed(embed).components(components);
+            return Ok(Some(
+                msg.channel_id.send_message(&self.ctx.http, message).await?,
+            ));
+        }
+
+        let http = &self.ctx.http;
+        let mut response = self.response.lock().await;
+        let message = match *response {
+            Response::None => {
+                let message = CreateInteractionResponseMessage::new()
+                    .embed(embed)
+                    .components(components)
+                    .ephemeral(ephemeral);
+                with_interaction!(self.source, interaction => {
+                    interaction
+                        .create_response(http, CreateInteractionResponse::Message(message))
+                        .await?
+                });
+
+                if fetch {
+                    Some(with_interaction!(self.source, interaction => {
+                        interaction.get_response(http).await?
+                    }))
+                } else {
+                    None
+                }
+            }
+            // The thinking message is shown to everyone, so it can't turn into an error only the user sees
+            Response::Deferred if ephemeral => {
+                with_interaction!(self.source, interaction => {
+                    interaction.delete_response(http).await?
+                });
+                Some(self.followup(embed, components, ephemeral).await?)
+            }
+            Response::Deferred => {
+                let edit = EditInteractionResponse::new()
+                    .embed(embed)
+                    .components(components);
+                Some(with_interaction!(self.source, interaction => {
+                    interaction.edit_response(http, edit).await?
+                }))
+            }
+            Response::Sent => Some(self.followup(embed, components, ephemeral).await?),
+        };
+
+        *response = Response::Sent;
+        Ok(message)
+    }
+
+    async fn followup(
+        &self,
+        embed: CreateEmbed,
+        components: Vec<CreateActionRow>,
+        ephemeral: bool,
+    ) -> Result<Message> {
+        let followup = CreateInteractionResponseFollowup::new()
+            .embed(embed)
+            .components(components)
+            .ephemeral(ephemeral);
+
+        Ok(with_interaction!(self.source, interaction => {
+            interaction.create_followup(&self.ctx.http, followup).await?
+        }))
+    }
+}
@@ -1 +1,3 @@
+pub mod events;
+pub mod invocation;
 pub mod roles;
@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 
+use anyhow::Result;
 use serenity::{
     model::{
         prelude::{GuildId, Member, Role},
@@ -37,6 +38,49 @@ async fn get_all_permissions(ctx: &Context, member: &Member) -> Permissions {
     permissions
 }
 
+/// Check if the member has the permission, administrators and the owner have every permission.
+/// The guild is fetched when it isn't cached, the member is assumed to lack the permission when
+/// that fails.
+pub async fn has_permission(ctx: &Context, member: &Member, permission: Permissions) -> bool {
+    let cached = member
+        .guild_id
+        .to_guild_cached(&ctx.cache)
+        .map(|guild| guild.member_permissions(member));
+
+    let permissions = match cached {
+        Some(permissions) => permissions,
+        None => match fetch_permissions(ctx, member).await {
+            Ok(permissions) => permissions,
+            Err(why) => {
+                println!(
+                    "Failed to get the permissions of {}: {:?}",
+                    member.user.name, why
+                );
+                return false;
+            }
+        },
+    };
+
+    permissions.administrator() || permissions.contains(permission)
+}
+
+/// The permissions of the member from the guild's roles, including the @everyone role
+async fn fetch_permissions(ctx: &Context, member: &Member) -> Result<Permissions> {
+    let guild = member.guild_id.to_partial_guild(&ctx.http).await?;
+    if guild.owner_id == member.user.id {
+        return Ok(Permissions::all());
+    }
+
+    Ok(guild
+        .roles
+        .values()
+        // The @everyone role has the same id as the guild
+        .filter(|role| role.id.get() == guild.id.get() || member.roles.contains(&role.id))
+        .fold(Permissions::empty(), |permissions, role| {
+            permissions | role.permissions
+        }))
+}
+
 pub async fn get_permissions(ctx: &Context, member: &Member) -> HashMap<String, bool> {
     let mut permissions: HashMap<String, bool> = HashMap::new();
 
@@ -3,6 +3,8 @@ use std::{collections::HashMap, sync::Arc};
 use serenity::{all::GuildId, prelude::TypeMapKey};
 use tokio::sync::Mutex;
 
+use crate::models::settings::GuildSettings;
+
 use super::music::manager::MusicManager;
 
 pub struct GuildManager {
@@ -19,11 +21,26 @@ impl GuildManager {
     pub fn get(&mut self, guild_id: &GuildId) -> &mut GuildData {
         self.guilds.entry(guild_id.get()).or_default()
     }
+
+    /// Same as [`GuildManager::get`], but `None` instead of adding the guild when it wasn't used yet
+    pub fn find(&self, guild_id: &GuildId) -> Option<&GuildData> {
+        self.guilds.get(&guild_id.get())
+    }
+
+    /// The music manager of every guild that has been used so far
+    pub fn music_managers(&self) -> Vec<(GuildId, Arc<Mutex<MusicManager>>)> {
+        self.guilds
+            .iter()
+            .map(|(id, data)| (GuildId::new(*id), Arc::clone(&data.music)))
+            .collect()
+    }
 }
 
 #[derive(Default)]
 pub struct GuildData {
     pub music: Arc<Mutex<MusicManager>>,
+    /// The settings of the guild once they were loaded from the database
+    pub settings: Option<GuildSettings>,
 }
 
 pub struct GuildContext;
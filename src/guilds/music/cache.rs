@@ -0,0 +1,336 @@
+//! Keeps the audio of recently played and upcoming tracks on disk, so they start without a gap
+//! and don't have to be downloaded again. The least recently played audio is removed once the
+//! cache grows too large.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use anyhow::Result;
+use serenity::{client::Context, prelude::TypeMapKey};
+
+use super::source::{self, Source};
+
+/// Extension of files that are still being downloaded
+const PARTIAL_EXTENSION: &str = "download";
+
+pub struct AudioCache {
+    directory: PathBuf,
+    /// How large the cache may grow, in bytes
+    max_size: u64,
+    state: Mutex<CacheState>,
+}
+
+#[derive(Default)]
+struct CacheState {
+    /// Cached files by their name
+    entries: HashMap<String, CacheEntry>,
+    /// The size of every cached file together, in bytes
+    size: u64,
+    /// Files that are being downloaded right now
+    downloading: HashSet<String>,
+}
+
+struct CacheEntry {
+    size: u64,
+    last_used: SystemTime,
+}
+
+impl AudioCache {
+    /// Opens the cache in the directory, picking up the files cached before the last restart
+    pub fn open(directory: PathBuf, max_size: u64) -> Result<Self> {
+        fs::create_dir_all(&directory)?;
+
+        let mut state = CacheState::default();
+        for entry in fs::read_dir(&directory)? {
+            let entry = entry?;
+            let path = entry.path();
+
+            // Downloads that were interrupted can't be resumed
+            if path
+                .extension()
+                .is_some_and(|extension| extension == PARTIAL_EXTENSION)
+            {
+                let _ = fs::remove_file(&path);
+                continue;
+            }
+
+            let metadata = entry.metadata()?;
+            state.size += metadata.len();
+            state.entries.insert(
+                entry.file_name().to_string_lossy().to_string(),
+                CacheEntry {
+                    size: metadata.len(),
+                    last_used: metadata.modified()?,
+                },
+            );
+        }
+
+        let cache = AudioCache {
+            directory,
+            max_size,
+            state: Mutex::new(state),
+        };
+        cache.evict(&mut cache.state.lock().unwrap());
+
+        Ok(cache)
+    }
+
+    /// The cached audio of the source, if it has been downloaded
+    pub fn get(&self, id: &str) -> Option<PathBuf> {
+        let name = file_name(id);
+        let mut state = self.state.lock().unwrap();
+        let entry = state.entries.get_mut(&name)?;
+
+        let path = self.directory.join(&name);
+        if !path.exists() {
+            let size = entry.size;
+            state.entries.remove(&name);
+            state.size -= size;
+            return None;
+        }
+
+        entry.last_used = SystemTime::now();
+        Some(path)
+    }
+
+    /// Removes the cached audio of the source, such as when it turned out to be broken
+    pub fn remove(&self, id: &str) {
+        let name = file_name(id);
+        let mut state = self.state.lock().unwrap();
+
+        if let Some(entry) = state.entries.remove(&name) {
+            state.size -= entry.size;
+            let _ = fs::remove_file(self.directory.join(name));
+        }
+    }
+
+    /// Downloads the audio of the source in the background, unless it's already cached or on its way
+    pub fn prefetch(self: &Arc<Self>, source: Arc<dyn Source>) {
+        if !source.cacheable() {
+            return;
+        }
+
+        let name = file_name(&source.id());
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.entries.contains_key(&name) || !state.downloading.insert(name.clone()) {
+                return;
+            }
+        }
+
+        let cache = Arc::clone(self);
+        tokio::spawn(async move {
+            if let Err(why) = cache.download(&*source, &name).await {
+                println!("Failed to cache {}: {:?}", source.id(), why);
+            }
+
+            cache.state.lock().unwrap().downloading.remove(&name);
+        });
+    }
+
+    async fn download(&self, source: &dyn Source, name: &str) -> Result<()> {
+        let partial = self
+            .directory
+            .join(format!("{}.{}", name, PARTIAL_EXTENSION));
+
+        if let Err(why) = source.download(&partial).await {
+            let _ = tokio::fs::remove_file(&partial).await;
+            return Err(why);
+        }
+
+        let size = tokio::fs::metadata(&partial).await?.len();
+        if size > self.max_size {
+            tokio::fs::remove_file(&partial).await?;
+            return Ok(());
+        }
+
+        tokio::fs::rename(&partial, self.directory.join(name)).await?;
+
+        let mut state = self.state.lock().unwrap();
+        state.size += size;
+        state.entries.insert(
+            name.to_string(),
+            CacheEntry {
+                size,
+                last_used: SystemTime::now(),
+            },
+        );
+        self.evict(&mut state);
+
+        Ok(())
+    }
+
+    /// Removes the least recently used files until the cache fits in its size again
+    fn evict(&self, state: &mut CacheState) {
+        while state.size > self.max_size {
+            let Some(name) = state
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(name, _)| name.clone())
+            else {
+                break;
+            };
+
+            let entry = state.entries.remove(&name).expect("Entry was just found");
+            state.size -= entry.size;
+            let _ = fs::remove_file(self.directory.join(name));
+        }
+    }
+}
+
+pub struct CacheKey;
+
+impl TypeMapKey for CacheKey {
+    type Value = Arc<AudioCache>;
+}
+
+/// The audio cache, `None` if caching is disabled
+pub async fn get(ctx: &Context) -> Option<Arc<AudioCache>> {
+    let typemap = ctx.data.read().await;
+    typemap.get::<CacheKey>().cloned()
+}
+
+/// A file name that is the same for every run, source ids can contain characters such as slashes
+/// so they are hashed instead of used directly
+fn file_name(id: &str) -> String {
+    // FNV-1a, the standard library's hasher isn't guaranteed to stay the same between releases
+    let hash = id.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+
+    format!("{}-{:016x}", source::kind(id), hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, time::Duration};
+
+    use anyhow::bail;
+    use async_trait::async_trait;
+    use reqwest::Client;
+    use songbird::input::Input;
+
+    use super::{source::Metadata, *};
+
+    /// A source that downloads the given number of bytes
+    struct FakeSource {
+        id: String,
+        size: usize,
+    }
+
+    impl FakeSource {
+        fn new(id: &str, size: usize) -> Self {
+            FakeSource {
+                id: id.to_string(),
+                size,
+            }
+        }
+    }
+
+    #[async_trait]
+    impl Source for FakeSource {
+        fn id(&self) -> String {
+            self.id.clone()
+        }
+
+        fn url(&self) -> Option<String> {
+            None
+        }
+
+        fn metadata(&self) -> Metadata {
+            Metadata::default()
+        }
+
+        async fn input(&self, _client: &Client) -> Result<Input> {
+            bail!("Nothing plays in tests")
+        }
+
+        fn cacheable(&self) -> bool {
+            true
+        }
+
+        async fn download(&self, path: &Path) -> Result<()> {
+            tokio::fs::write(path, vec![0; self.size]).await?;
+            Ok(())
+        }
+    }
+
+    /// An empty directory for the test to cache in
+    fn directory(test: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("prismatic-cache-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        directory
+    }
+
+    async fn download(cache: &AudioCache, source: FakeSource) {
+        let name = file_name(&source.id);
+        cache.download(&source, &name).await.unwrap();
+
+        // Files used right after each other should still be told apart
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    #[tokio::test]
+    async fn evicts_the_least_recently_used_audio() {
+        let directory = directory("evict");
+        let cache = AudioCache::open(directory.clone(), 100).unwrap();
+
+        download(&cache, FakeSource::new("test:first", 40)).await;
+        download(&cache, FakeSource::new("test:second", 40)).await;
+        assert!(cache.get("test:first").is_some());
+        download(&cache, FakeSource::new("test:third", 40)).await;
+
+        assert!(cache.get("test:first").is_some());
+        assert!(cache.get("test:second").is_none());
+        assert!(cache.get("test:third").is_some());
+        assert!(!directory.join(file_name("test:second")).exists());
+        assert_eq!(cache.state.lock().unwrap().size, 80);
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn skips_audio_larger_than_the_cache() {
+        let directory = directory("oversized");
+        let cache = AudioCache::open(directory.clone(), 100).unwrap();
+
+        download(&cache, FakeSource::new("test:large", 150)).await;
+
+        assert!(cache.get("test:large").is_none());
+        assert_eq!(fs::read_dir(&directory).unwrap().count(), 0);
+        assert_eq!(cache.state.lock().unwrap().size, 0);
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn removes_interrupted_downloads_on_startup() {
+        let directory = directory("startup");
+        fs::create_dir_all(&directory).unwrap();
+
+        let kept = directory.join(file_name("test:kept"));
+        let partial = directory.join(format!(
+            "{}.{}",
+            file_name("test:partial"),
+            PARTIAL_EXTENSION
+        ));
+        fs::write(&kept, [0; 10]).unwrap();
+        fs::write(&partial, [0; 10]).unwrap();
+
+        let cache = AudioCache::open(directory.clone(), 100).unwrap();
+
+        assert!(!partial.exists());
+        assert_eq!(cache.get("test:kept"), Some(kept));
+        assert!(cache.get("test:partial").is_none());
+        assert_eq!(cache.state.lock().unwrap().size, 10);
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
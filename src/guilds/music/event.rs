@@ -1,14 +1,31 @@
+use anyhow::Result;
 use async_trait::async_trait;
+use serenity::all::ChannelId;
+use songbird::tracks::TrackHandle;
 
-use super::track::Track;
+use super::{filter::Filters, track::Track};
 
 /// Handles music events and makes it so when music needs to play, it actually plays
 #[async_trait]
 #[allow(unused_variables)]
 pub trait MusicEventHandler: Send {
-    async fn on_track_start(&mut self, track: &Track) {}
+    /// The text channel the handler sends its messages in
+    fn channel(&self) -> Option<ChannelId> {
+        None
+    }
+
+    /// Starts playing the track with the filters applied, returning a handle to control it while it plays
+    async fn on_track_start(&mut self, track: &Track, filters: &Filters) -> Result<TrackHandle>;
     async fn on_track_end(&mut self, track: &Track) {}
+    /// Called when the track couldn't be played, `retrying` tells whether it will be played again
+    async fn on_track_failed(&mut self, track: &Track, reason: &str, retrying: bool) {}
+    /// Called with the track that plays after the current one, so it can be prepared ahead of time
+    async fn on_track_upcoming(&mut self, track: &Track) {}
+    /// Called when autoplay picks the track after this one, so related tracks can be found while it
+    /// plays and handed to [`MusicManager::set_related`](super::manager::MusicManager::set_related)
+    async fn on_autoplay_upcoming(&mut self, track: &Track) {}
     async fn on_track_skipped(&mut self) {}
     async fn on_queue_added(&mut self, track: &Track) {}
+    async fn on_tracks_added(&mut self, tracks: &[Track]) {}
     async fn on_queue_emptied(&mut self) {}
 }
@@ -0,0 +1,97 @@
+//! Counts tracks failing to play for each kind of source, so the owner hears about a source that broke
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use serenity::{client::Context, prelude::TypeMapKey};
+
+use crate::helper::{embed, helper::SendEmbed};
+
+use super::source;
+
+/// How many tracks of the same kind of source have to fail in a row before the owner is told
+const REPORT_THRESHOLD: u32 = 5;
+
+#[derive(Default)]
+pub struct FailureTracker {
+    /// Failures in a row, keyed by the kind of source such as `youtube`
+    failures: HashMap<String, u32>,
+}
+
+impl FailureTracker {
+    /// Records a failure, returning how many failed in a row if it's time to tell the owner
+    pub fn failed(&mut self, kind: &str) -> Option<u32> {
+        let failures = self.failures.entry(kind.to_string()).or_default();
+        *failures += 1;
+
+        (*failures % REPORT_THRESHOLD == 0).then_some(*failures)
+    }
+
+    pub fn succeeded(&mut self, kind: &str) {
+        self.failures.remove(kind);
+    }
+}
+
+pub struct FailureKey;
+
+impl TypeMapKey for FailureKey {
+    type Value = Arc<Mutex<FailureTracker>>;
+}
+
+/// Records a track of the source failing, and lets the owner know once it keeps happening
+pub async fn record_failure(ctx: &Context, source: &str, reason: &str) {
+    let kind = source::kind(source);
+    let failures = tracker(ctx)
+        .await
+        .lock()
+        .expect("Failure tracker was poisoned")
+        .failed(kind);
+
+    if let Some(failures) = failures {
+        let message = format!(
+            "**{}** tracks from `{}` failed to play in a row, the last error was:\n```{}```",
+            failures, kind, reason
+        );
+
+        if let Err(why) = notify_owner(ctx, message).await {
+            println!("Failed to notify the owner about failing tracks: {:?}", why);
+        }
+    }
+}
+
+pub async fn record_success(ctx: &Context, source: &str) {
+    tracker(ctx)
+        .await
+        .lock()
+        .expect("Failure tracker was poisoned")
+        .succeeded(source::kind(source));
+}
+
+/// The tracker is shared behind its own lock, since failures are recorded while a music manager is
+/// locked and shouldn't have to wait on the whole type map
+async fn tracker(ctx: &Context) -> Arc<Mutex<FailureTracker>> {
+    let typemap = ctx.data.read().await;
+    typemap
+        .get::<FailureKey>()
+        .expect("Expected FailureKey in TypeMap.")
+        .clone()
+}
+
+async fn notify_owner(ctx: &Context, message: String) -> serenity::Result<()> {
+    let info = ctx.http.get_current_application_info().await?;
+    let owner = match (info.team, info.owner) {
+        (Some(team), _) => team.owner_user_id,
+        (None, Some(owner)) => owner.id,
+        (None, None) => return Ok(()),
+    };
+
+    let channel = owner.create_dm_channel(ctx).await?;
+    channel
+        .id
+        .send_embed(&ctx.http, embed::error(message))
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,552 @@
+//! Audio filters such as bass boost and nightcore. Filtered and normalized tracks are decoded here
+//! and the resulting PCM is handed to songbird as raw audio.
+
+use std::{
+    fmt,
+    io::{self, Read, Seek, SeekFrom},
+};
+
+use anyhow::{anyhow, Result};
+use songbird::input::{
+    codecs::{get_codec_registry, get_probe},
+    Input, LiveInput, Parsed, RawAdapter,
+};
+use symphonia::core::{
+    audio::SampleBuffer,
+    errors::Error as SymphoniaError,
+    formats::{SeekMode, SeekTo},
+    io::MediaSource,
+    units::Time,
+};
+
+use super::loudness::{Normalization, Normalizer};
+
+/// Center frequencies of the equalizer bands in Hz
+pub const EQ_BANDS: [f32; 10] = [
+    31.0, 62.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0,
+];
+/// The highest boost or cut of the bass boost and equalizer bands, in decibels
+pub const MAX_GAIN: f32 = 12.0;
+pub const MIN_SPEED: f32 = 0.5;
+pub const MAX_SPEED: f32 = 2.0;
+
+/// Frequency below which the bass boost applies, in Hz
+const BASS_FREQUENCY: f32 = 100.0;
+/// How many frames the time stretcher works on at once
+const STRETCH_FRAME: usize = 2048;
+
+/// The filters applied to every track of a guild
+#[derive(Clone, PartialEq)]
+pub struct Filters {
+    /// Gain of the bass in decibels, 0 is off
+    pub bass_boost: f32,
+    /// Changes the speed along with the pitch
+    pub style: Option<Style>,
+    /// Changes the speed without changing the pitch, 1.0 is the original speed
+    pub speed: f32,
+    /// Gain of each of the `EQ_BANDS` in decibels
+    pub equalizer: [f32; EQ_BANDS.len()],
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Style {
+    /// Faster and higher pitched
+    Nightcore,
+    /// Slower and lower pitched
+    Vaporwave,
+}
+
+impl Style {
+    fn rate(&self) -> f64 {
+        match self {
+            Style::Nightcore => 1.25,
+            Style::Vaporwave => 0.8,
+        }
+    }
+}
+
+impl fmt::Display for Style {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Style::Nightcore => write!(f, "nightcore"),
+            Style::Vaporwave => write!(f, "vaporwave"),
+        }
+    }
+}
+
+impl Filters {
+    pub fn is_active(&self) -> bool {
+        *self != Filters::default()
+    }
+
+    /// How much faster the track plays than the original, positions in the track have to be
+    /// divided by this to get the position of the filtered audio
+    pub fn time_scale(&self) -> f64 {
+        self.style.map_or(1.0, |style| style.rate()) * self.speed as f64
+    }
+
+    /// Wraps the input so it plays with the filters and normalization applied,
+    /// it's left alone if there is nothing to apply
+    pub async fn apply(&self, input: Input, normalization: Option<Normalization>) -> Result<Input> {
+        if !self.is_active() && normalization.is_none() {
+            return Ok(input);
+        }
+
+        let input = input
+            .make_playable_async(get_codec_registry(), get_probe())
+            .await
+            .map_err(|e| anyhow!("Failed to read the track: {:?}", e))?;
+
+        let Input::Live(LiveInput::Parsed(parsed), _) = input else {
+            return Err(anyhow!("Failed to read the track"));
+        };
+
+        let params = parsed.decoder.codec_params();
+        let sample_rate = params
+            .sample_rate
+            .ok_or_else(|| anyhow!("The track has no sample rate"))?;
+        let channels = params
+            .channels
+            .ok_or_else(|| anyhow!("The track has no channels"))?
+            .count();
+
+        let source = FilteredSource {
+            chain: FilterChain::new(self, normalization, sample_rate, channels),
+            parsed,
+            sample_rate,
+            channels,
+            speed: self.speed as f64,
+            buffer: Vec::new(),
+            offset: 0,
+            position: 0,
+        };
+
+        // Claiming a different sample rate makes songbird resample it, changing speed and pitch together
+        let rate = self.style.map_or(1.0, |style| style.rate());
+        let claimed_rate = (sample_rate as f64 * rate).round() as u32;
+
+        Ok(RawAdapter::new(source, claimed_rate, channels as u32).into())
+    }
+}
+
+impl Default for Filters {
+    fn default() -> Self {
+        Filters {
+            bass_boost: 0.0,
+            style: None,
+            speed: 1.0,
+            equalizer: [0.0; EQ_BANDS.len()],
+        }
+    }
+}
+
+impl fmt::Display for Filters {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut active = Vec::new();
+
+        if self.bass_boost != 0.0 {
+            active.push(format!("bass boost {:+} dB", self.bass_boost));
+        }
+        if let Some(style) = self.style {
+            active.push(style.to_string());
+        }
+        if self.speed != 1.0 {
+            active.push(format!("speed {}x", self.speed));
+        }
+        if self.equalizer.iter().any(|gain| *gain != 0.0) {
+            active.push("equalizer".to_string());
+        }
+
+        if active.is_empty() {
+            write!(f, "none")
+        } else {
+            write!(f, "{}", active.join(", "))
+        }
+    }
+}
+
+/// Decodes the track and runs the samples through the filters, producing interleaved f32 PCM
+struct FilteredSource {
+    parsed: Parsed,
+    chain: FilterChain,
+    sample_rate: u32,
+    channels: usize,
+    speed: f64,
+    /// Filtered audio that hasn't been read yet
+    buffer: Vec<u8>,
+    offset: usize,
+    /// How many bytes of filtered audio came before the buffer
+    position: u64,
+}
+
+impl FilteredSource {
+    /// Decodes and filters the next packet, returns `false` at the end of the track
+    fn fill(&mut self) -> io::Result<bool> {
+        loop {
+            let packet = match self.parsed.format.next_packet() {
+                Ok(packet) => packet,
+                Err(SymphoniaError::IoError(e)) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    self.chain.finish();
+                    return Ok(false);
+                }
+                Err(e) => return Err(io::Error::other(e)),
+            };
+
+            if packet.track_id() != self.parsed.track_id {
+                continue;
+            }
+
+            let decoded = match self.parsed.decoder.decode(&packet) {
+                Ok(decoded) => decoded,
+                // A single broken packet isn't worth stopping the track for
+                Err(SymphoniaError::DecodeError(_)) => continue,
+                Err(e) => return Err(io::Error::other(e)),
+            };
+
+            let mut samples = SampleBuffer::<f32>::new(decoded.capacity() as u64, *decoded.spec());
+            samples.copy_interleaved_ref(decoded);
+
+            let filtered = self.chain.process(samples.samples());
+            self.position += self.buffer.len() as u64;
+            self.buffer = filtered
+                .iter()
+                .flat_map(|sample| sample.to_le_bytes())
+                .collect();
+            self.offset = 0;
+
+            if !self.buffer.is_empty() {
+                return Ok(true);
+            }
+        }
+    }
+}
+
+impl Read for FilteredSource {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.offset == self.buffer.len() {
+            if !self.fill()? {
+                return Ok(0);
+            }
+        }
+
+        let read = buf.len().min(self.buffer.len() - self.offset);
+        buf[..read].copy_from_slice(&self.buffer[self.offset..self.offset + read]);
+        self.offset += read;
+
+        Ok(read)
+    }
+}
+
+impl Seek for FilteredSource {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let current = self.position + self.offset as u64;
+        let target = match pos {
+            SeekFrom::Start(target) => target,
+            SeekFrom::Current(0) => return Ok(current),
+            SeekFrom::Current(offset) => current.saturating_add_signed(offset),
+            SeekFrom::End(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "The length of filtered audio is unknown",
+                ))
+            }
+        };
+
+        // The filtered audio is shorter or longer than the original when the speed changed
+        let frame = target / (4 * self.channels as u64);
+        let seconds = frame as f64 * self.speed / self.sample_rate as f64;
+
+        self.parsed
+            .format
+            .seek(
+                SeekMode::Accurate,
+                SeekTo::Time {
+                    time: Time::from(seconds),
+                    track_id: Some(self.parsed.track_id),
+                },
+            )
+            .map_err(|e| io::Error::other(e))?;
+
+        self.parsed.decoder.reset();
+        self.chain.reset();
+        self.buffer.clear();
+        self.offset = 0;
+        self.position = target;
+
+        Ok(target)
+    }
+}
+
+impl MediaSource for FilteredSource {
+    fn is_seekable(&self) -> bool {
+        true
+    }
+
+    fn byte_len(&self) -> Option<u64> {
+        None
+    }
+}
+
+struct FilterChain {
+    channels: usize,
+    normalizer: Option<Normalizer>,
+    /// The equalizer and bass boost filters of every channel
+    biquads: Vec<Vec<Biquad>>,
+    stretch: Option<TimeStretch>,
+}
+
+impl FilterChain {
+    fn new(
+        filters: &Filters,
+        normalization: Option<Normalization>,
+        sample_rate: u32,
+        channels: usize,
+    ) -> Self {
+        let normalizer = normalization
+            .map(|normalization| Normalizer::new(normalization, sample_rate, channels));
+        let sample_rate = sample_rate as f32;
+        let mut biquads = Vec::new();
+
+        if filters.bass_boost != 0.0 {
+            biquads.push(Biquad::low_shelf(
+                sample_rate,
+                BASS_FREQUENCY,
+                filters.bass_boost,
+            ));
+        }
+
+        for (frequency, gain) in EQ_BANDS.iter().zip(filters.equalizer) {
+            // Bands above the nyquist frequency can't be represented
+            if gain != 0.0 && *frequency < sample_rate / 2.0 {
+                biquads.push(Biquad::peaking(sample_rate, *frequency, gain));
+            }
+        }
+
+        FilterChain {
+            channels,
+            normalizer,
+            biquads: vec![biquads; channels],
+            stretch: (filters.speed != 1.0).then(|| TimeStretch::new(filters.speed, channels)),
+        }
+    }
+
+    fn process(&mut self, samples: &[f32]) -> Vec<f32> {
+        let mut samples = samples.to_vec();
+
+        // Loudness is measured before any filter, so the measurement is the same whichever are active
+        if let Some(normalizer) = &mut self.normalizer {
+            normalizer.process(&mut samples);
+        }
+
+        for frame in samples.chunks_mut(self.channels) {
+            for (sample, filters) in frame.iter_mut().zip(&mut self.biquads) {
+                for filter in filters.iter_mut() {
+                    *sample = filter.process(*sample);
+                }
+
+                *sample = sample.clamp(-1.0, 1.0);
+            }
+        }
+
+        match &mut self.stretch {
+            Some(stretch) => stretch.process(&samples),
+            None => samples,
+        }
+    }
+
+    fn reset(&mut self) {
+        for filter in self.biquads.iter_mut().flatten() {
+            filter.reset();
+        }
+
+        if let Some(normalizer) = &mut self.normalizer {
+            normalizer.reset();
+        }
+
+        if let Some(stretch) = &mut self.stretch {
+            stretch.reset();
+        }
+    }
+
+    /// Called once the whole track has been decoded
+    fn finish(&mut self) {
+        if let Some(normalizer) = &mut self.normalizer {
+            normalizer.finish();
+        }
+    }
+}
+
+/// A second order filter, the coefficients come from the Audio EQ Cookbook
+#[derive(Clone)]
+pub(super) struct Biquad {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+    z1: f32,
+    z2: f32,
+}
+
+impl Biquad {
+    fn new(b0: f32, b1: f32, b2: f32, a0: f32, a1: f32, a2: f32) -> Self {
+        Biquad {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+            z1: 0.0,
+            z2: 0.0,
+        }
+    }
+
+    fn peaking(sample_rate: f32, frequency: f32, gain: f32) -> Self {
+        let a = 10f32.powf(gain / 40.0);
+        let w0 = 2.0 * std::f32::consts::PI * frequency / sample_rate;
+        // A Q of 1/sqrt(2), wide enough for neighbouring bands to blend together
+        let alpha = w0.sin() / (2.0 * std::f32::consts::FRAC_1_SQRT_2);
+
+        Biquad::new(
+            1.0 + alpha * a,
+            -2.0 * w0.cos(),
+            1.0 - alpha * a,
+            1.0 + alpha / a,
+            -2.0 * w0.cos(),
+            1.0 - alpha / a,
+        )
+    }
+
+    fn low_shelf(sample_rate: f32, frequency: f32, gain: f32) -> Self {
+        let a = 10f32.powf(gain / 40.0);
+        let w0 = 2.0 * std::f32::consts::PI * frequency / sample_rate;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / 2.0 * std::f32::consts::SQRT_2;
+        let beta = 2.0 * a.sqrt() * alpha;
+
+        Biquad::new(
+            a * ((a + 1.0) - (a - 1.0) * cos + beta),
+            2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+            a * ((a + 1.0) - (a - 1.0) * cos - beta),
+            (a + 1.0) + (a - 1.0) * cos + beta,
+            -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+            (a + 1.0) + (a - 1.0) * cos - beta,
+        )
+    }
+
+    /// The K-weighting used to measure loudness, a high shelf followed by a high pass. The
+    /// coefficients are worked out for the sample rate from the filters of ITU-R BS.1770, which
+    /// aren't quite the shelf and high pass of the cookbook.
+    pub(super) fn k_weighting(sample_rate: f32) -> [Biquad; 2] {
+        let (sample_rate, pi) = (sample_rate as f64, std::f64::consts::PI);
+
+        let k = (pi * 1681.974450955533 / sample_rate).tan();
+        let q = 0.7071752369554196;
+        let vh = 10f64.powf(3.999843853973347 / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let shelf = Biquad::new(
+            (vh + vb * k / q + k * k) as f32,
+            (2.0 * (k * k - vh)) as f32,
+            (vh - vb * k / q + k * k) as f32,
+            (1.0 + k / q + k * k) as f32,
+            (2.0 * (k * k - 1.0)) as f32,
+            (1.0 - k / q + k * k) as f32,
+        );
+
+        let k = (pi * 38.13547087602444 / sample_rate).tan();
+        let q = 0.5003270373238773;
+        let a0 = 1.0 + k / q + k * k;
+        let pass = Biquad::new(
+            1.0,
+            -2.0,
+            1.0,
+            1.0,
+            (2.0 * (k * k - 1.0) / a0) as f32,
+            ((1.0 - k / q + k * k) / a0) as f32,
+        );
+
+        [shelf, pass]
+    }
+
+    pub(super) fn process(&mut self, input: f32) -> f32 {
+        let output = self.b0 * input + self.z1;
+        self.z1 = self.b1 * input - self.a1 * output + self.z2;
+        self.z2 = self.b2 * input - self.a2 * output;
+        output
+    }
+
+    fn reset(&mut self) {
+        self.z1 = 0.0;
+        self.z2 = 0.0;
+    }
+}
+
+/// Changes the speed without changing the pitch by overlapping windowed frames of the input
+/// at a different rate than they are read
+struct TimeStretch {
+    speed: f64,
+    channels: usize,
+    window: Vec<f32>,
+    /// Interleaved input that hasn't been fully used yet
+    input: Vec<f32>,
+    /// Where the next frame starts in the input, in frames
+    position: f64,
+    /// The overlapping output frames being added together
+    overlap: Vec<f32>,
+}
+
+impl TimeStretch {
+    fn new(speed: f32, channels: usize) -> Self {
+        // A Hann window adds up to exactly one when frames overlap by half
+        let window = (0..STRETCH_FRAME)
+            .map(|i| {
+                let phase = 2.0 * std::f32::consts::PI * i as f32 / STRETCH_FRAME as f32;
+                0.5 - 0.5 * phase.cos()
+            })
+            .collect();
+
+        TimeStretch {
+            speed: speed as f64,
+            channels,
+            window,
+            input: Vec::new(),
+            position: 0.0,
+            overlap: vec![0.0; STRETCH_FRAME * channels],
+        }
+    }
+
+    fn process(&mut self, samples: &[f32]) -> Vec<f32> {
+        let channels = self.channels;
+        let hop = STRETCH_FRAME / 2;
+        let mut output = Vec::new();
+
+        self.input.extend_from_slice(samples);
+
+        while (self.position as usize + STRETCH_FRAME) * channels <= self.input.len() {
+            let start = self.position as usize * channels;
+            let frame = &self.input[start..start + STRETCH_FRAME * channels];
+
+            for (i, samples) in frame.chunks(channels).enumerate() {
+                for (channel, sample) in samples.iter().enumerate() {
+                    self.overlap[i * channels + channel] += sample * self.window[i];
+                }
+            }
+
+            output.extend(self.overlap.drain(..hop * channels));
+            self.overlap.resize(STRETCH_FRAME * channels, 0.0);
+
+            self.position += hop as f64 * self.speed;
+            let used = self.position as usize;
+            self.input.drain(..used * channels);
+            self.position -= used as f64;
+        }
+
+        output
+    }
+
+    fn reset(&mut self) {
+        self.input.clear();
+        self.position = 0.0;
+        self.overlap.fill(0.0);
+    }
+}
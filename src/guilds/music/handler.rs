@@ -1,43 +1,38 @@
 //! Handles communication between the music manager and songbird.
 
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
+use anyhow::Result;
 use async_trait::async_trait;
 use reqwest::Client;
 use serenity::{
     all::{ChannelId, GuildId},
     client::Context,
 };
-use songbird::{Event, EventContext, EventHandler, Songbird};
+use songbird::{tracks::TrackHandle, Event, EventContext, EventHandler, Songbird};
 use tokio::sync::Mutex;
 
-use crate::helper::{embed, emoji, helper::SendEmbed, music};
+use crate::{
+    helper::{embed, helper::SendEmbed, music, settings},
+    SourceKey,
+};
 
 use super::{
-    event::MusicEventHandler, manager::Event as MusicEvent, manager::MusicManager, track::Track,
+    cache, event::MusicEventHandler, failures, filter::Filters, idle, loudness,
+    manager::Event as MusicEvent, manager::MusicManager, player, track::Track,
 };
 
+/// How often the position of a playing track is checked, for crossfading into the next one
+const POSITION_INTERVAL: Duration = Duration::from_millis(250);
+
 pub struct MusicHandler {
     guild: GuildId,
     channel: ChannelId,
     context: Context,
-    config: MusicConfig,
     songbird: Arc<Songbird>,
     client: Arc<Client>,
 }
 
-pub struct MusicConfig {
-    pub announce_songs: bool,
-}
-
-impl MusicConfig {
-    pub fn new() -> Self {
-        MusicConfig {
-            announce_songs: true,
-        }
-    }
-}
-
 impl MusicHandler {
     pub fn new(
         ctx: Context,
@@ -47,7 +42,6 @@ impl MusicHandler {
         channel: ChannelId,
     ) -> Self {
         Self {
-            config: MusicConfig::new(),
             context: ctx,
             guild,
             channel,
@@ -55,38 +49,138 @@ impl MusicHandler {
             songbird,
         }
     }
+
+    /// Whether the guild wants to see what's playing, which it does unless it turned it off
+    async fn announces_songs(&self) -> bool {
+        settings::get_settings(&self.context, self.guild)
+            .await
+            .map_or(true, |settings| settings.announce_songs)
+    }
 }
 
 #[async_trait]
 impl MusicEventHandler for MusicHandler {
-    async fn on_track_start(&mut self, track: &Track) {
-        let _ = music::play_track(
+    fn channel(&self) -> Option<ChannelId> {
+        Some(self.channel)
+    }
+
+    async fn on_track_start(&mut self, track: &Track, filters: &Filters) -> Result<TrackHandle> {
+        let cache = cache::get(&self.context).await;
+        let settings = settings::get_settings(&self.context, self.guild).await.ok();
+        let normalization = match &settings {
+            Some(settings) if settings.normalize => {
+                Some(loudness::prepare(&self.context, track.source.id()).await)
+            }
+            _ => None,
+        };
+
+        let handle = music::play_track(
             Arc::clone(&self.songbird),
             Arc::clone(&self.client),
             self.guild,
             &track,
+            cache.as_deref(),
+            filters,
+            normalization,
         )
-        .await;
-
-        if self.config.announce_songs {
-            let thumbnail = track.thumbnail.clone();
-            let embed = embed::build(format!(
-                "{} Now playing **{}**",
-                emoji::get_bot_emote(&self.context, "p_music")
-                    .await
-                    .unwrap(),
-                track.title
-            ));
-
-            if let Some(thumbnail) = thumbnail {
-                let _ = self
-                    .channel
-                    .send_embed(&self.context.http, embed.image(thumbnail))
-                    .await;
-            } else {
-                let _ = self.channel.send_embed(&self.context.http, embed).await;
-            };
+        .await?;
+
+        // Positions are only needed to crossfade, which most guilds leave off
+        if settings
+            .as_ref()
+            .is_some_and(|settings| !settings.crossfade().is_zero())
+        {
+            let _ = handle.add_event(
+                Event::Periodic(POSITION_INTERVAL, None),
+                PositionNotifier {
+                    handler: music::get_manager(&self.context, self.guild).await,
+                },
+            );
+        }
+
+        // Keep the audio around in case it's played again
+        if let Some(cache) = cache {
+            cache.prefetch(Arc::clone(&track.source));
+        }
+
+        if settings.is_none_or(|settings| settings.announce_songs) {
+            let _ = handle.add_event(
+                Event::Periodic(player::UPDATE_INTERVAL, None),
+                PlayerNotifier {
+                    context: self.context.clone(),
+                    guild: self.guild,
+                },
+            );
+
+            player::refresh(&self.context, self.guild);
         }
+
+        Ok(handle)
+    }
+
+    async fn on_track_end(&mut self, track: &Track) {
+        failures::record_success(&self.context, &track.source.id()).await;
+    }
+
+    async fn on_track_failed(&mut self, track: &Track, reason: &str, retrying: bool) {
+        failures::record_failure(&self.context, &track.source.id(), reason).await;
+
+        // The cached audio might be what's broken, so the retry gets a fresh stream
+        if let Some(cache) = cache::get(&self.context).await {
+            cache.remove(&track.source.id());
+        }
+
+        let action = if retrying {
+            "trying again"
+        } else {
+            "skipping it"
+        };
+
+        let _ = self
+            .channel
+            .send_embed(
+                &self.context.http,
+                embed::error(format!(
+                    "Could not play {}, {}\n`{}`",
+                    track.linked_title(),
+                    action,
+                    reason
+                )),
+            )
+            .await;
+    }
+
+    async fn on_track_upcoming(&mut self, track: &Track) {
+        if let Some(cache) = cache::get(&self.context).await {
+            cache.prefetch(Arc::clone(&track.source));
+        }
+    }
+
+    async fn on_autoplay_upcoming(&mut self, track: &Track) {
+        // Looking them up takes a while, the music manager shouldn't stay locked in the meantime
+        let context = self.context.clone();
+        let guild = self.guild;
+        let track = track.clone();
+        tokio::spawn(async move {
+            let sources = {
+                let typemap = context.data.read().await;
+                typemap
+                    .get::<SourceKey>()
+                    .expect("Expected SourceKey in TypeMap.")
+                    .clone()
+            };
+
+            let related = match sources.related(&track.source.id()).await {
+                Ok(related) => related,
+                Err(why) => {
+                    println!("Failed to find tracks for autoplay: {:?}", why);
+                    return;
+                }
+            };
+
+            let manager = music::get_manager(&context, guild).await;
+            manager.lock().await.set_related(&track, related);
+        });
     }
 
     async fn on_queue_added(&mut self, track: &Track) {
@@ -94,11 +188,40 @@ impl MusicEventHandler for MusicHandler {
             .channel
             .send_embed(
                 &self.context.http,
-                embed::build(format!("Added **{}** to the queue", track.title)),
+                embed::build(format!(
+                    "Added {} `{}` to the queue",
+                    track.linked_title(),
+                    track.display_duration()
+                )),
+            )
+            .await;
+    }
+
+    async fn on_tracks_added(&mut self, tracks: &[Track]) {
+        let _ = self
+            .channel
+            .send_embed(
+                &self.context.http,
+                embed::build(format!("Added **{}** tracks to the queue", tracks.len())),
             )
             .await;
     }
 
+    async fn on_queue_emptied(&mut self) {
+        if self.announces_songs().await {
+            let _ = self
+                .channel
+                .send_embed(
+                    &self.context.http,
+                    embed::build("The queue has finished, add more tracks to keep listening"),
+                )
+                .await;
+        }
+
+        player::refresh(&self.context, self.guild);
+        idle::start_idle_timer(self.context.clone(), self.guild).await;
+    }
+
     async fn on_track_skipped(&mut self) {
         let _ = music::stop_playing(Arc::clone(&self.songbird), self.guild).await;
     }
@@ -117,8 +240,14 @@ impl TrackEndNotifier {
 #[async_trait]
 impl EventHandler for TrackEndNotifier {
     async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
-        if let EventContext::Track(_) = ctx {
+        if let EventContext::Track(tracks) = ctx {
             let mut manager = self.handler.lock().await;
+
+            // Tracks that were skipped or stopped have already been replaced
+            if !tracks.iter().any(|(_, handle)| manager.is_current(handle)) {
+                return None;
+            }
+
             manager.emit(MusicEvent::TrackEnded).await;
             manager.next().await;
         }
@@ -126,3 +255,37 @@ impl EventHandler for TrackEndNotifier {
         None
     }
 }
+
+/// Tells the music manager how far along a track is while it plays
+struct PositionNotifier {
+    handler: Arc<Mutex<MusicManager>>,
+}
+
+#[async_trait]
+impl EventHandler for PositionNotifier {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        if let EventContext::Track(tracks) = ctx {
+            let mut manager = self.handler.lock().await;
+
+            for (state, handle) in *tracks {
+                manager.update_position(handle, state.position).await;
+            }
+        }
+
+        None
+    }
+}
+
+/// Keeps the progress bar of the player up to date while a track plays
+struct PlayerNotifier {
+    context: Context,
+    guild: GuildId,
+}
+
+#[async_trait]
+impl EventHandler for PlayerNotifier {
+    async fn act(&self, _ctx: &EventContext<'_>) -> Option<Event> {
+        player::refresh(&self.context, self.guild);
+        None
+    }
+}
@@ -0,0 +1,123 @@
+//! Leaves the voice channel when nothing is playing or nobody is listening anymore
+
+use std::time::Duration;
+
+use serenity::{
+    all::{GuildId, VoiceState},
+    client::Context,
+};
+
+use crate::{
+    config::EmptyAction,
+    helper::{music, settings},
+    ConfigKey,
+};
+
+/// How long to wait before leaving an empty voice channel, in case someone only rejoins
+const EMPTY_GRACE_PERIOD: Duration = Duration::from_secs(30);
+
+/// Leaves the voice channel once nothing has been playing for the configured idle timeout
+pub async fn start_idle_timer(ctx: Context, guild: GuildId) {
+    let timeout = idle_timeout(&ctx).await;
+
+    tokio::spawn(async move {
+        tokio::time::sleep(timeout).await;
+
+        let manager = music::get_manager(&ctx, guild).await;
+        let idle = manager
+            .lock()
+            .await
+            .idle_time()
+            .is_some_and(|idle| idle >= timeout);
+
+        if idle && !is_always_connected(&ctx, guild).await {
+            let _ = music::leave(&ctx, guild).await;
+        }
+    });
+}
+
+/// Pauses or leaves when every listener left the bot's voice channel, and resumes when one comes back
+pub async fn on_voice_state_update(ctx: &Context, old: Option<&VoiceState>, new: &VoiceState) {
+    let Some(guild) = new.guild_id else {
+        return;
+    };
+
+    let bot = ctx.cache.current_user().id;
+    if new.user_id == bot {
+        // The bot was disconnected, whether it left by itself or was kicked
+        if new.channel_id.is_none() {
+            let manager = music::get_manager(ctx, guild).await;
+            manager.lock().await.reset();
+        }
+
+        return;
+    }
+
+    // Only people joining or leaving the bot's channel matter
+    let Some(channel) = music::get_bot_channel(ctx, guild) else {
+        return;
+    };
+
+    let old_channel = old.and_then(|state| state.channel_id);
+    if old_channel != Some(channel) && new.channel_id != Some(channel) {
+        return;
+    }
+
+    let manager = music::get_manager(ctx, guild).await;
+    if !music::get_listeners(ctx, guild).is_empty() {
+        let mut music = manager.lock().await;
+        if music.is_auto_paused() && music.resume().is_ok() {
+            music.set_auto_paused(false);
+        }
+
+        return;
+    }
+
+    let action = {
+        let typemap = ctx.data.read().await;
+        typemap
+            .get::<ConfigKey>()
+            .expect("Expected ConfigKey in TypeMap.")
+            .music
+            .empty_action
+    };
+
+    let wait = match action {
+        EmptyAction::Leave => EMPTY_GRACE_PERIOD,
+        EmptyAction::Pause => {
+            let mut music = manager.lock().await;
+            if music.now_playing().is_some() && music.pause().is_ok() {
+                music.set_auto_paused(true);
+            }
+
+            idle_timeout(ctx).await
+        }
+    };
+
+    let ctx = ctx.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(wait).await;
+
+        let empty = music::get_listeners(&ctx, guild).is_empty();
+        if empty && !is_always_connected(&ctx, guild).await {
+            let _ = music::leave(&ctx, guild).await;
+        }
+    });
+}
+
+async fn idle_timeout(ctx: &Context) -> Duration {
+    let typemap = ctx.data.read().await;
+    let minutes = typemap
+        .get::<ConfigKey>()
+        .expect("Expected ConfigKey in TypeMap.")
+        .music
+        .idle_timeout;
+
+    Duration::from_secs(minutes * 60)
+}
+
+async fn is_always_connected(ctx: &Context, guild: GuildId) -> bool {
+    settings::get_settings(ctx, guild)
+        .await
+        .is_ok_and(|settings| settings.always_connected)
+}
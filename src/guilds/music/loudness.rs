@@ -0,0 +1,298 @@
+//! Plays tracks at about the same loudness. Loudness is measured like EBU R128 does, and saved
+//! per source once a track was heard from start to end, so later plays are right from the start.
+
+use std::collections::VecDeque;
+
+use serenity::client::Context;
+use tokio::runtime::Handle;
+
+use crate::{models::loudness::Loudness, DatabaseKey};
+
+use super::filter::Biquad;
+
+/// The loudness every track is brought to, in LUFS
+const TARGET: f64 = -14.0;
+/// The most a track is made louder or quieter, in decibels
+const MAX_GAIN: f64 = 12.0;
+/// Blocks quieter than this are silence and don't count towards the loudness, in LUFS
+const ABSOLUTE_GATE: f64 = -70.0;
+/// Blocks this much quieter than the average don't count towards the loudness, in LU
+const RELATIVE_GATE: f64 = 10.0;
+/// Block loudness is counted in bins of this size from the absolute gate up, like libebur128 does,
+/// so measuring a long track takes as much memory as a short one. In LU.
+const BIN_SIZE: f64 = 0.1;
+/// How many bins there are, reaching up to +30 LUFS
+const BINS: usize = 1000;
+/// How many 100ms steps to listen to before changing the volume of a track that wasn't measured yet
+const MIN_STEPS: usize = 30;
+/// How many steps to wait between estimates while a track is being measured
+const ESTIMATE_INTERVAL: usize = 10;
+/// How long it takes for the volume to move to a new estimate, in seconds
+const SMOOTHING: f32 = 1.0;
+
+/// What is known about the loudness of a track before it plays
+pub struct Normalization {
+    /// Loudness measured when the track played before
+    known: Option<f64>,
+    /// Saves the loudness once the whole track was measured
+    on_measured: Box<dyn FnOnce(f64) + Send + Sync>,
+}
+
+/// Looks up the loudness of the source, and prepares to save it if it wasn't measured before
+pub async fn prepare(ctx: &Context, source: String) -> Normalization {
+    let database = {
+        let typemap = ctx.data.read().await;
+        typemap
+            .get::<DatabaseKey>()
+            .expect("Expected DatabaseKey in TypeMap.")
+            .clone()
+    };
+
+    let known = {
+        let mut connection = database.lock().await;
+        Loudness::get(&mut connection, &source).ok().flatten()
+    };
+
+    // Decoding happens outside of the runtime, so saving has to be sent back to it
+    let runtime = Handle::current();
+    let on_measured = move |integrated| {
+        runtime.spawn(async move {
+            let mut connection = database.lock().await;
+            if let Err(why) = (Loudness { source, integrated }).save(&mut connection) {
+                println!("Failed to save loudness: {:?}", why);
+            }
+        });
+    };
+
+    Normalization {
+        known,
+        on_measured: Box::new(on_measured),
+    }
+}
+
+/// Changes the volume of decoded audio to bring it to the target loudness
+pub struct Normalizer {
+    meter: LoudnessMeter,
+    known: Option<f64>,
+    on_measured: Option<Box<dyn FnOnce(f64) + Send + Sync>>,
+    /// Whether the track has been heard from the start without skipping anything
+    complete: bool,
+    gain: f32,
+    target_gain: f32,
+    /// How far the gain moves towards the target every frame
+    smoothing: f32,
+}
+
+impl Normalizer {
+    pub fn new(normalization: Normalization, sample_rate: u32, channels: usize) -> Self {
+        let gain = normalization.known.map_or(1.0, gain_for);
+
+        Normalizer {
+            meter: LoudnessMeter::new(sample_rate, channels),
+            known: normalization.known,
+            on_measured: Some(normalization.on_measured),
+            complete: true,
+            gain,
+            target_gain: gain,
+            smoothing: 1.0 / (sample_rate as f32 * SMOOTHING),
+        }
+    }
+
+    pub fn process(&mut self, samples: &mut [f32]) {
+        if self.known.is_none() && self.meter.process(samples) {
+            let steps = self.meter.steps;
+            if steps >= MIN_STEPS && steps % ESTIMATE_INTERVAL == 0 {
+                if let Some(loudness) = self.meter.integrated() {
+                    self.target_gain = gain_for(loudness);
+                }
+            }
+        }
+
+        for frame in samples.chunks_mut(self.meter.channels) {
+            self.gain += (self.target_gain - self.gain) * self.smoothing;
+            for sample in frame {
+                *sample *= self.gain;
+            }
+        }
+    }
+
+    /// Called after seeking, the measurement is no longer of the whole track
+    pub fn reset(&mut self) {
+        self.complete = false;
+    }
+
+    /// Saves the loudness once the whole track was measured
+    pub fn finish(&mut self) {
+        if !self.complete || self.known.is_some() {
+            return;
+        }
+
+        if let (Some(loudness), Some(on_measured)) =
+            (self.meter.integrated(), self.on_measured.take())
+        {
+            on_measured(loudness);
+        }
+    }
+}
+
+/// The linear gain that brings audio of the loudness to the target
+fn gain_for(loudness: f64) -> f32 {
+    let gain = (TARGET - loudness).clamp(-MAX_GAIN, MAX_GAIN);
+    10f64.powf(gain / 20.0) as f32
+}
+
+/// Measures the integrated loudness of audio in LUFS, as described by ITU-R BS.1770
+struct LoudnessMeter {
+    channels: usize,
+    /// The K-weighting filters of every channel
+    filters: Vec<[Biquad; 2]>,
+    /// How many frames make up 100ms
+    step_size: usize,
+    sum: f64,
+    frames: usize,
+    /// How many 100ms steps were measured
+    steps: usize,
+    /// The mean square of the last steps, four steps make up a block
+    recent: VecDeque<f64>,
+    /// How many blocks fell in each bin of loudness, blocks under the absolute gate are left out
+    histogram: Vec<u64>,
+}
+
+impl LoudnessMeter {
+    fn new(sample_rate: u32, channels: usize) -> Self {
+        LoudnessMeter {
+            channels,
+            filters: vec![Biquad::k_weighting(sample_rate as f32); channels],
+            step_size: (sample_rate / 10) as usize,
+            sum: 0.0,
+            frames: 0,
+            steps: 0,
+            recent: VecDeque::with_capacity(4),
+            histogram: vec![0; BINS],
+        }
+    }
+
+    /// Measures the samples, returns whether a step was completed
+    fn process(&mut self, samples: &[f32]) -> bool {
+        let mut completed = false;
+
+        for frame in samples.chunks(self.channels) {
+            for (sample, [shelf, pass]) in frame.iter().zip(&mut self.filters) {
+                let weighted = pass.process(shelf.process(*sample)) as f64;
+                self.sum += weighted * weighted;
+            }
+
+            self.frames += 1;
+            if self.frames == self.step_size {
+                self.complete_step(self.sum / self.step_size as f64);
+                self.sum = 0.0;
+                self.frames = 0;
+                completed = true;
+            }
+        }
+
+        completed
+    }
+
+    /// Blocks are 400ms long and overlap by 75%, so every step after the third completes a block
+    fn complete_step(&mut self, power: f64) {
+        self.steps += 1;
+        if self.recent.len() == 4 {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(power);
+
+        if self.recent.len() < 4 {
+            return;
+        }
+
+        let block = loudness(self.recent.iter().sum::<f64>() / 4.0);
+        if block > ABSOLUTE_GATE {
+            let bin = ((block - ABSOLUTE_GATE) / BIN_SIZE) as usize;
+            self.histogram[bin.min(BINS - 1)] += 1;
+        }
+    }
+
+    /// The loudness of everything measured so far, `None` if it was too short or silent
+    fn integrated(&self) -> Option<f64> {
+        let blocks = self.histogram.iter().sum::<u64>();
+        if blocks == 0 {
+            return None;
+        }
+
+        let gate = loudness(self.summed_power(0) / blocks as f64) - RELATIVE_GATE;
+        let first = ((gate - ABSOLUTE_GATE) / BIN_SIZE).max(0.0) as usize;
+        let gated = self.histogram.iter().skip(first).sum::<u64>();
+
+        Some(loudness(self.summed_power(first) / gated as f64))
+    }
+
+    /// The power of the blocks from the bin up added together, a block counts as the middle of its bin
+    fn summed_power(&self, first: usize) -> f64 {
+        self.histogram
+            .iter()
+            .enumerate()
+            .skip(first)
+            .map(|(bin, count)| {
+                let loudness = ABSOLUTE_GATE + (bin as f64 + 0.5) * BIN_SIZE;
+                *count as f64 * power(loudness)
+            })
+            .sum()
+    }
+}
+
+fn loudness(power: f64) -> f64 {
+    -0.691 + 10.0 * power.log10()
+}
+
+/// The mean square of K-weighted audio with the loudness
+fn power(loudness: f64) -> f64 {
+    10f64.powf((loudness + 0.691) / 10.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 48000;
+
+    /// A stereo 1 kHz sine at the level in dBFS
+    fn sine(level: f64, seconds: usize) -> Vec<f32> {
+        let amplitude = 10f64.powf(level / 20.0);
+        (0..SAMPLE_RATE as usize * seconds)
+            .flat_map(|frame| {
+                let time = frame as f64 / SAMPLE_RATE as f64;
+                let sample =
+                    (amplitude * (2.0 * std::f64::consts::PI * 1000.0 * time).sin()) as f32;
+                [sample, sample]
+            })
+            .collect()
+    }
+
+    fn measure(parts: &[Vec<f32>]) -> Option<f64> {
+        let mut meter = LoudnessMeter::new(SAMPLE_RATE, 2);
+        for samples in parts {
+            meter.process(samples);
+        }
+
+        meter.integrated()
+    }
+
+    #[test]
+    fn measures_a_sine() {
+        // A stereo sine at -23 dBFS is -23 LUFS, the first test case of EBU Tech 3341
+        let loudness = measure(&[sine(-23.0, 20)]).unwrap();
+        assert!((loudness - -23.0).abs() < 0.1, "measured {} LUFS", loudness);
+    }
+
+    #[test]
+    fn quiet_parts_are_gated() {
+        let loudness = measure(&[sine(-36.0, 10), sine(-23.0, 60), sine(-36.0, 10)]).unwrap();
+        assert!((loudness - -23.0).abs() < 0.1, "measured {} LUFS", loudness);
+    }
+
+    #[test]
+    fn silence_has_no_loudness() {
+        assert_eq!(measure(&[vec![0.0; SAMPLE_RATE as usize * 4]]), None);
+    }
+}
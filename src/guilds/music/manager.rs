@@ -1,4 +1,22 @@
-use super::{event::MusicEventHandler, track::Track};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use rand::seq::SliceRandom;
+use serenity::all::{ChannelId, MessageId, UserId};
+use songbird::tracks::{PlayMode, TrackHandle};
+
+use super::{event::MusicEventHandler, filter::Filters, track::Track};
+
+/// How many tracks are kept around for `previous`
+const HISTORY_SIZE: usize = 10;
+/// How many related tracks autoplay tries at most when they fail to play, before giving up
+const AUTOPLAY_ATTEMPTS: usize = 3;
 
 pub struct MusicManager {
     queue: Vec<Track>,
@@ -6,32 +24,148 @@ pub struct MusicManager {
     playing: Option<Track>,
     handler: Option<Box<dyn MusicEventHandler>>,
     music_loop: Loop,
+    track_handle: Option<TrackHandle>,
+    volume: f32,
+    skip_votes: HashSet<UserId>,
+    /// The message showing the progress of the vote to skip the current track
+    skip_message: Option<(ChannelId, MessageId)>,
+    /// The message showing what is playing, edited whenever the music changes
+    player_message: Option<(ChannelId, MessageId)>,
+    /// When the queue ran out, `None` while something is playing
+    idle_since: Option<Instant>,
+    /// Whether the track was paused because everyone left the voice channel
+    auto_paused: bool,
+    /// Whether anything changed since the queue was last saved
+    dirty: bool,
+    /// Whether the current track already failed once and was played again
+    retried: bool,
+    filters: Filters,
+    /// How long the end of a track overlaps with the start of the next
+    crossfade: Duration,
+    /// The previous track while it fades out, along with when it ends
+    fading_out: Option<(TrackHandle, Duration)>,
+    /// Whether the current track is fading in, because it started during a crossfade
+    fading_in: bool,
+    /// Keep playing related tracks when the queue runs out
+    autoplay: bool,
+    /// Tracks related to the current one that autoplay picks from, found while the current one plays
+    related: Vec<Track>,
+    /// Let requesters take turns instead of playing tracks in the order they were added
+    fair: bool,
 }
 
 #[allow(dead_code)]
 impl MusicManager {
     pub async fn add(&mut self, track: &Track) {
-        self.queue.push(track.clone());
+        self.enqueue(track.clone());
+        self.dirty = true;
         self.emit(Event::QueueAdded(track.clone())).await;
 
         // If we aren't playing, let's start playing
         if self.playing.is_none() {
             self.next().await;
+        } else {
+            self.prepare_upcoming().await;
+        }
+    }
+
+    /// Adds several tracks at once, announcing them as a single addition
+    pub async fn add_all(&mut self, tracks: &[Track]) {
+        for track in tracks {
+            self.enqueue(track.clone());
+        }
+        self.dirty = true;
+        self.emit(Event::TracksAdded(tracks.to_vec())).await;
+
+        if self.playing.is_none() {
+            self.next().await;
+        } else {
+            self.prepare_upcoming().await;
+        }
+    }
+
+    /// Removes the track at the given index, returns `None` if the index is out of range
+    pub fn remove(&mut self, index: usize) -> Option<Track> {
+        if index >= self.queue.len() {
+            return None;
+        }
+
+        self.dirty = true;
+        Some(self.queue.remove(index))
+    }
+
+    /// Moves a track from one index to another, returns the moved track or `None` if
+    /// either index is out of range
+    pub fn move_track(&mut self, from: usize, to: usize) -> Option<&Track> {
+        if from >= self.queue.len() || to >= self.queue.len() {
+            return None;
+        }
+
+        let track = self.queue.remove(from);
+        self.queue.insert(to, track);
+        self.dirty = true;
+        self.queue.get(to)
+    }
+
+    /// Puts the queue in a random order, in fair mode requesters still take turns
+    pub fn shuffle(&mut self) {
+        self.queue.shuffle(&mut rand::thread_rng());
+        if self.fair {
+            self.rebalance();
+        }
+
+        self.dirty = true;
+    }
+
+    pub fn fair(&self) -> bool {
+        self.fair
+    }
+
+    /// Turns fair mode on or off, turning it on reorders the queue so requesters take turns
+    pub fn set_fair(&mut self, fair: bool) {
+        if fair && !self.fair {
+            self.fair = true;
+            self.rebalance();
+            self.dirty = true;
         }
+
+        self.fair = fair;
+    }
+
+    /// Adds a track to the end of the queue, or in fair mode to the end of its requester's turn
+    fn enqueue(&mut self, track: Track) {
+        let index = if self.fair {
+            fair_index(
+                self.queue.iter().map(|track| track.requester),
+                track.requester,
+            )
+        } else {
+            self.queue.len()
+        };
+
+        self.queue.insert(index, track);
     }
 
-    pub fn remove(&mut self, index: usize) {
-        self.queue.remove(index);
+    /// Puts the queue in fair order, keeping the order of each requester's tracks
+    fn rebalance(&mut self) {
+        for track in std::mem::take(&mut self.queue) {
+            self.enqueue(track);
+        }
     }
 
     pub fn clear(&mut self) {
         self.queue.clear();
+        self.dirty = true;
     }
 
     pub fn get(&self, index: usize) -> Option<&Track> {
         self.queue.get(index)
     }
 
+    pub fn tracks(&self) -> &[Track] {
+        &self.queue
+    }
+
     pub fn len(&self) -> usize {
         self.queue.len()
     }
@@ -46,52 +180,495 @@ impl MusicManager {
 
     pub fn set_loop(&mut self, loop_type: Loop) {
         self.music_loop = loop_type;
+        self.dirty = true;
     }
 
     pub fn get_loop(&self) -> &Loop {
         &self.music_loop
     }
 
+    pub fn track_handle(&self) -> Option<&TrackHandle> {
+        self.track_handle.as_ref()
+    }
+
+    /// Check if the handle belongs to the track that is currently playing
+    pub fn is_current(&self, handle: &TrackHandle) -> bool {
+        self.track_handle
+            .as_ref()
+            .is_some_and(|current| current.uuid() == handle.uuid())
+    }
+
+    pub fn pause(&self) -> Result<()> {
+        self.current_handle()?.pause()?;
+        Ok(())
+    }
+
+    pub fn resume(&self) -> Result<()> {
+        self.current_handle()?.play()?;
+        Ok(())
+    }
+
+    /// Seeks to the position in the original track, regardless of the filters changing its speed
+    pub async fn seek(&self, position: Duration) -> Result<Duration> {
+        let scale = self.filters.time_scale();
+        let position = self
+            .current_handle()?
+            .seek_async(position.div_f64(scale))
+            .await?;
+
+        Ok(position.mul_f64(scale))
+    }
+
+    /// Get the position in the original track and the paused state of the current track
+    pub async fn playback_state(&self) -> Result<(Duration, bool)> {
+        let state = self.current_handle()?.get_info().await?;
+        Ok((
+            state.position.mul_f64(self.filters.time_scale()),
+            state.playing == PlayMode::Pause,
+        ))
+    }
+
+    pub fn filters(&self) -> &Filters {
+        &self.filters
+    }
+
+    /// Changes the filters of the current and all following tracks,
+    /// the current track is restarted with the new filters from where it was
+    pub async fn set_filters(&mut self, filters: Filters) {
+        let state = self.playback_state().await.ok();
+        self.filters = filters;
+
+        let Some((position, paused)) = state else {
+            return;
+        };
+
+        self.emit(Event::TrackSkipped).await;
+        self.track_handle = None;
+
+        if !self.start_current().await {
+            self.play_next(true).await;
+            return;
+        }
+
+        let _ = self.seek(position).await;
+        if paused {
+            let _ = self.pause();
+        }
+    }
+
+    pub fn volume(&self) -> f32 {
+        self.volume
+    }
+
+    /// Sets the volume for the current and all following tracks, where 1.0 is the original volume
+    pub fn set_volume(&mut self, volume: f32) -> Result<()> {
+        self.volume = volume;
+        self.dirty = true;
+        if let Some(handle) = &self.track_handle {
+            handle.set_volume(volume)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn autoplay(&self) -> bool {
+        self.autoplay
+    }
+
+    /// Turns autoplay on or off, related tracks are looked for right away when nothing is queued
+    pub async fn set_autoplay(&mut self, autoplay: bool) {
+        self.autoplay = autoplay;
+        self.related.clear();
+        if self.playing.is_some() {
+            self.prepare_upcoming().await;
+        }
+    }
+
+    /// Remembers tracks related to the track for autoplay, unless another track started since
+    pub fn set_related(&mut self, track: &Track, related: Vec<Track>) {
+        if self.playing.as_ref() == Some(track) {
+            self.related = related;
+        }
+    }
+
+    pub fn crossfade(&self) -> Duration {
+        self.crossfade
+    }
+
+    pub fn set_crossfade(&mut self, crossfade: Duration) {
+        self.crossfade = crossfade;
+    }
+
+    /// Called regularly while tracks play, fades between tracks and starts the next track
+    /// once the current one is within the crossfade of its end
+    pub async fn update_position(&mut self, handle: &TrackHandle, position: Duration) {
+        if self.crossfade.is_zero() {
+            return;
+        }
+
+        let crossfade = self.crossfade.as_secs_f64();
+        if let Some((fading, end)) = &self.fading_out {
+            if fading.uuid() == handle.uuid() {
+                let progress = 1.0 - end.saturating_sub(position).as_secs_f64() / crossfade;
+                if progress >= 1.0 {
+                    self.stop_fading();
+                } else {
+                    let _ = fading.set_volume(self.volume * fade_out(progress));
+                }
+
+                return;
+            }
+        }
+
+        if !self.is_current(handle) {
+            return;
+        }
+
+        if self.fading_in {
+            let progress = position.as_secs_f64() / crossfade;
+            let _ = handle.set_volume(self.volume * fade_in(progress));
+            self.fading_in = progress < 1.0;
+        }
+
+        // Positions are of the filtered audio, which is shorter or longer when the speed changed.
+        // Live streams never end, so there is nothing to fade out of.
+        let Some(end) = self
+            .playing
+            .as_ref()
+            .filter(|track| track.source.seekable())
+            .and_then(|track| track.duration)
+            .filter(|duration| !duration.is_zero())
+            .map(|duration| duration.div_f64(self.filters.time_scale()))
+        else {
+            return;
+        };
+
+        let has_next = !self.queue.is_empty()
+            || self.music_loop != Loop::None
+            || (self.autoplay && !self.related.is_empty());
+        if end.saturating_sub(position) > self.crossfade || !has_next {
+            return;
+        }
+
+        // The current track keeps playing while the next one starts
+        self.stop_fading();
+        self.fading_out = Some((handle.clone(), end));
+        self.advance(false).await;
+
+        if let Some(handle) = &self.track_handle {
+            let _ = handle.set_volume(0.0);
+            self.fading_in = true;
+        }
+    }
+
+    /// Stops the track that is fading out, it would otherwise keep playing silently until it ends
+    fn stop_fading(&mut self) {
+        if let Some((fading, _)) = self.fading_out.take() {
+            let _ = fading.stop();
+        }
+    }
+
+    /// Stops the current track and clears the queue
+    pub async fn stop(&mut self) {
+        if self.playing.is_some() {
+            self.emit(Event::TrackSkipped).await;
+            self.finish_current(true);
+        }
+
+        self.stop_fading();
+        self.queue.clear();
+        self.related.clear();
+        if self.idle_since.is_none() {
+            self.set_idle().await;
+        }
+    }
+
+    /// Forgets the queue, current track and handler without notifying the handler, used when the bot
+    /// is no longer connected to a voice channel. The next join attaches a handler with the settings
+    /// of that moment.
+    pub fn reset(&mut self) {
+        self.finish_current(true);
+        self.handler = None;
+        self.stop_fading();
+        self.queue.clear();
+        self.related.clear();
+        self.autoplay = false;
+        self.idle_since = None;
+        self.dirty = true;
+    }
+
+    /// How long nothing has been playing, `None` if something is playing
+    pub fn idle_time(&self) -> Option<Duration> {
+        self.idle_since.map(|since| since.elapsed())
+    }
+
+    pub fn is_auto_paused(&self) -> bool {
+        self.auto_paused
+    }
+
+    pub fn set_auto_paused(&mut self, auto_paused: bool) {
+        self.auto_paused = auto_paused;
+    }
+
+    /// Adds a vote to skip the current track, returns `false` if the user already voted
+    pub fn vote_skip(&mut self, user: UserId) -> bool {
+        self.skip_votes.insert(user)
+    }
+
+    pub fn skip_votes(&self) -> &HashSet<UserId> {
+        &self.skip_votes
+    }
+
+    pub fn skip_message(&self) -> Option<(ChannelId, MessageId)> {
+        self.skip_message
+    }
+
+    pub fn set_skip_message(&mut self, channel: ChannelId, message: MessageId) {
+        self.skip_message = Some((channel, message));
+    }
+
+    pub fn player_message(&self) -> Option<(ChannelId, MessageId)> {
+        self.player_message
+    }
+
+    pub fn set_player_message(&mut self, channel: ChannelId, message: MessageId) {
+        self.player_message = Some((channel, message));
+    }
+
+    /// Forgets the player message, so the next track gets a new one
+    pub fn take_player_message(&mut self) -> Option<(ChannelId, MessageId)> {
+        self.player_message.take()
+    }
+
     pub async fn skip(&mut self) {
         self.emit(Event::TrackSkipped).await;
-        self.next().await;
+        self.advance(true).await;
     }
 
     pub async fn next(&mut self) -> Option<Track> {
-        if self.queue.is_empty() {
-            self.playing = None;
-            return None;
+        self.advance(false).await
+    }
+
+    async fn advance(&mut self, skipped: bool) -> Option<Track> {
+        let was_playing = self.playing.is_some();
+        self.finish_current(skipped);
+        self.play_next(was_playing).await
+    }
+
+    /// Plays the first track of the queue that can be played
+    async fn play_next(&mut self, mut was_playing: bool) -> Option<Track> {
+        let mut autoplayed = 0;
+
+        loop {
+            if self.queue.is_empty() {
+                if !was_playing || autoplayed == AUTOPLAY_ATTEMPTS || !self.queue_related() {
+                    break;
+                }
+
+                autoplayed += 1;
+            }
+
+            let track = self.queue.remove(0);
+            self.playing = Some(track.clone());
+            self.idle_since = None;
+            self.dirty = true;
+
+            if self.start_current().await {
+                return Some(track);
+            }
+
+            was_playing = true;
+        }
+
+        if was_playing {
+            self.set_idle().await;
         }
 
-        let track = self.queue.remove(0);
-        self.playing = Some(track.clone());
+        None
+    }
+
+    /// Queues a track related to the last played one when autoplay is on, returns whether one was found.
+    /// The related tracks were found while the last one played, tracks from the history are skipped.
+    fn queue_related(&mut self) -> bool {
+        if !self.autoplay {
+            return false;
+        }
+
+        let previous = &self.previous;
+        let Some(index) = self
+            .related
+            .iter()
+            .position(|track| !previous.contains(track))
+        else {
+            return false;
+        };
+
+        let track = self.related.remove(index);
+        self.queue.push(track);
+        true
+    }
+
+    /// Starts the current track, it's retried once with a fresh stream if it fails to play and dropped
+    /// if it fails again. Returns whether the track is playing.
+    async fn start_current(&mut self) -> bool {
+        let Some(track) = self.playing.clone() else {
+            return false;
+        };
+
         self.emit(Event::TrackStarted(track.clone())).await;
+        if self.track_handle.is_none() && self.handler.is_some() {
+            self.retried = true;
+            self.emit(Event::TrackStarted(track)).await;
+        }
 
-        self.previous.push(track.clone());
-        if self.previous.len() > 10 {
-            self.previous.remove(0);
+        if self.track_handle.is_some() || self.handler.is_none() {
+            self.prepare_upcoming().await;
+            return true;
         }
 
-        match self.music_loop {
-            Loop::Queue => {
-                self.queue.push(track.clone());
-                Some(track)
+        self.discard_current();
+        false
+    }
+
+    /// Lets the handler get the next track ready while the current one plays, or find related tracks
+    /// when autoplay has to pick the next one
+    async fn prepare_upcoming(&mut self) {
+        // Tracks related to the previous track don't belong after this one
+        self.related.clear();
+        if let Some(track) = self.queue.first().cloned() {
+            self.emit(Event::TrackUpcoming(track)).await;
+        } else if self.autoplay && self.music_loop == Loop::None {
+            if let Some(track) = self.playing.clone() {
+                self.emit(Event::AutoplayUpcoming(track)).await;
             }
-            Loop::Track => {
-                self.queue.insert(0, track.clone());
-                Some(track)
+        }
+    }
+
+    /// Handles the current track failing while it plays, it's retried once from where it stopped
+    /// and the next track is played if it fails again. Sources that can't seek restart the track.
+    pub async fn fail_current(&mut self, reason: String, position: Duration) {
+        if self.playing.is_none() {
+            return;
+        }
+
+        self.emit(Event::TrackFailed(reason)).await;
+        self.track_handle = None;
+
+        if !self.retried {
+            self.retried = true;
+
+            let track = self.playing.clone().expect("No track playing");
+            let resume = track.source.seekable() && !position.is_zero();
+            self.emit(Event::TrackStarted(track)).await;
+            if let Some(handle) = &self.track_handle {
+                if resume {
+                    let _ = handle.seek(position);
+                }
+                return;
             }
-            _ => Some(track),
         }
+
+        self.discard_current();
+        self.play_next(true).await;
     }
 
-    pub fn previous(&mut self) -> Option<Track> {
-        if let Some(track) = self.previous.pop() {
-            self.queue.insert(0, track.clone());
-            Some(track)
-        } else {
-            None
+    async fn set_idle(&mut self) {
+        self.idle_since = Some(Instant::now());
+        self.emit(Event::QueueEmptied).await;
+    }
+
+    /// Goes back to the last played track, the current track is put back at the front of the queue
+    pub async fn previous(&mut self) -> Option<Track> {
+        let track = self.previous.pop()?;
+
+        // Looping the queue put the track back in the queue as well, it shouldn't be in there twice
+        if self.music_loop == Loop::Queue {
+            let looped = self
+                .queue
+                .iter()
+                .rposition(|queued| Arc::ptr_eq(&queued.source, &track.source));
+
+            if let Some(index) = looped {
+                self.queue.remove(index);
+            }
+        }
+
+        if let Some(current) = self.playing.take() {
+            self.emit(Event::TrackSkipped).await;
+            self.queue.insert(0, current);
+        }
+
+        self.queue.insert(0, track.clone());
+        self.next().await;
+
+        Some(track)
+    }
+
+    /// Moves the currently playing track into the history and re-queues it depending on the loop mode,
+    /// a skipped track is never looped on its own
+    fn finish_current(&mut self, skipped: bool) {
+        let Some(track) = self.discard_current() else {
+            return;
+        };
+
+        match self.music_loop {
+            Loop::Track if !skipped => self.queue.insert(0, track.clone()),
+            Loop::Queue => self.enqueue(track.clone()),
+            _ => {}
         }
+
+        self.previous.push(track);
+        if self.previous.len() > HISTORY_SIZE {
+            self.previous.remove(0);
+        }
+    }
+
+    /// Stops tracking the current track, without putting it in the history or looping it.
+    /// A track that can't be played is dropped this way, so looping the queue won't retry it forever.
+    fn discard_current(&mut self) -> Option<Track> {
+        self.track_handle = None;
+        self.skip_votes.clear();
+        self.skip_message = None;
+        self.auto_paused = false;
+        self.retried = false;
+        self.fading_in = false;
+
+        let track = self.playing.take()?;
+        self.dirty = true;
+        Some(track)
+    }
+
+    fn current_handle(&self) -> Result<&TrackHandle> {
+        self.track_handle
+            .as_ref()
+            .ok_or_else(|| anyhow!("Nothing is playing right now!"))
+    }
+
+    /// Returns whether anything changed since the last call
+    pub fn take_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.dirty)
+    }
+
+    /// Replaces the queue with a saved one and starts playing from the saved position
+    pub async fn restore(
+        &mut self,
+        tracks: Vec<Track>,
+        music_loop: Loop,
+        volume: f32,
+        position: Duration,
+    ) {
+        self.queue = tracks;
+        self.music_loop = music_loop;
+        self.volume = volume;
+
+        if self.next().await.is_some() && !position.is_zero() {
+            let _ = self.seek(position).await;
+        }
+    }
+
+    /// The channel messages about the music are sent in
+    pub fn text_channel(&self) -> Option<ChannelId> {
+        self.handler.as_ref().and_then(|handler| handler.channel())
     }
 
     pub fn event_handler(&mut self, handler: Box<dyn MusicEventHandler>) {
@@ -106,15 +683,40 @@ impl MusicManager {
         if let Some(handler) = &mut self.handler {
             match event {
                 Event::TrackStarted(track) => {
-                    let _ = handler.on_track_start(&track).await;
+                    match handler.on_track_start(&track, &self.filters).await {
+                        Ok(handle) => {
+                            let _ = handle.set_volume(self.volume);
+                            self.track_handle = Some(handle);
+                        }
+                        Err(why) => {
+                            self.track_handle = None;
+                            let _ = handler
+                                .on_track_failed(&track, &why.to_string(), !self.retried)
+                                .await;
+                        }
+                    }
                 }
                 Event::TrackEnded => {
                     let currently_playing = self.playing.clone().expect("No track playing");
                     let _ = handler.on_track_end(&currently_playing).await;
                 }
+                Event::TrackFailed(reason) => {
+                    if let Some(track) = &self.playing {
+                        let _ = handler.on_track_failed(track, &reason, !self.retried).await;
+                    }
+                }
+                Event::TrackUpcoming(track) => {
+                    let _ = handler.on_track_upcoming(&track).await;
+                }
+                Event::AutoplayUpcoming(track) => {
+                    let _ = handler.on_autoplay_upcoming(&track).await;
+                }
                 Event::QueueAdded(track) => {
                     let _ = handler.on_queue_added(&track).await;
                 }
+                Event::TracksAdded(tracks) => {
+                    let _ = handler.on_tracks_added(&tracks).await;
+                }
                 Event::TrackSkipped => {
                     let _ = handler.on_track_skipped().await;
                 }
@@ -126,6 +728,35 @@ impl MusicManager {
     }
 }
 
+/// Where a track of the requester goes in a queue with tracks of the given requesters, so every
+/// requester gets a turn each round. A requester's n-th track plays in round n, after every track of
+/// the same or an earlier round.
+fn fair_index(queue: impl Iterator<Item = Option<UserId>>, requester: Option<UserId>) -> usize {
+    let mut counts = HashMap::new();
+    let rounds = queue
+        .map(|queued| {
+            let count = counts.entry(queued).or_insert(0);
+            *count += 1;
+            *count - 1
+        })
+        .collect::<Vec<usize>>();
+
+    let round = counts.get(&requester).copied().unwrap_or(0);
+    rounds
+        .iter()
+        .rposition(|queued| *queued <= round)
+        .map_or(0, |index| index + 1)
+}
+
+/// The volume of a fading out track, equal power curves keep the loudness steady during the fade
+fn fade_out(progress: f64) -> f32 {
+    (progress.clamp(0.0, 1.0) * std::f64::consts::FRAC_PI_2).cos() as f32
+}
+
+fn fade_in(progress: f64) -> f32 {
+    (progress.clamp(0.0, 1.0) * std::f64::consts::FRAC_PI_2).sin() as f32
+}
+
 impl Default for MusicManager {
     fn default() -> Self {
         MusicManager {
@@ -134,26 +765,165 @@ impl Default for MusicManager {
             playing: None,
             music_loop: Loop::None,
             handler: None,
+            track_handle: None,
+            volume: 1.0,
+            skip_votes: HashSet::new(),
+            skip_message: None,
+            player_message: None,
+            idle_since: None,
+            auto_paused: false,
+            dirty: false,
+            retried: false,
+            filters: Filters::default(),
+            crossfade: Duration::ZERO,
+            fading_out: None,
+            fading_in: false,
+            autoplay: false,
+            related: Vec::new(),
+            fair: false,
         }
     }
 }
 
-#[derive(PartialEq)]
+#[derive(Clone, Copy, PartialEq)]
 pub enum Loop {
     None,
     Queue,
     Track,
 }
 
+impl FromStr for Loop {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" | "off" => Ok(Loop::None),
+            "queue" | "all" => Ok(Loop::Queue),
+            "track" | "song" => Ok(Loop::Track),
+            _ => Err(format!(
+                "`{}` is not a loop mode, use `none`, `track` or `queue`",
+                s
+            )),
+        }
+    }
+}
+
+impl Loop {
+    /// The mode after this one when cycling through them, such as with the button on the player
+    pub fn cycle(self) -> Loop {
+        match self {
+            Loop::None => Loop::Track,
+            Loop::Track => Loop::Queue,
+            Loop::Queue => Loop::None,
+        }
+    }
+}
+
+impl fmt::Display for Loop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Loop::None => write!(f, "none"),
+            Loop::Queue => write!(f, "queue"),
+            Loop::Track => write!(f, "track"),
+        }
+    }
+}
+
 pub enum Event {
     /// Sent when a track starts playing
     TrackStarted(Track),
     /// Sent when the current track ends
     TrackEnded,
+    /// Sent when the current track stops because of an error
+    TrackFailed(String),
+    /// Sent when the track that plays after the current one is known
+    TrackUpcoming(Track),
+    /// Sent when autoplay will pick the track after this one, as nothing else is queued
+    AutoplayUpcoming(Track),
     /// Sent when a track is added to the queue
     QueueAdded(Track),
+    /// Sent when multiple tracks are added to the queue at once, such as from a playlist
+    TracksAdded(Vec<Track>),
     /// Sent when the queue has no more tracks to play
     QueueEmptied,
     /// Sent when the current track is skipped
     TrackSkipped,
 }
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use crate::guilds::music::source::http::HttpSource;
+
+    use super::*;
+
+    /// Builds a queue in fair order from requesters adding tracks one after another
+    fn fair_queue(added: &[u64]) -> Vec<u64> {
+        let mut queue = Vec::new();
+        for requester in added {
+            let index = fair_index(
+                queue.iter().map(|queued| Some(UserId::new(*queued))),
+                Some(UserId::new(*requester)),
+            );
+            queue.insert(index, *requester);
+        }
+
+        queue
+    }
+
+    #[test]
+    fn interleaves_requesters() {
+        assert_eq!(fair_queue(&[1, 1, 1, 2, 2, 3]), vec![1, 2, 3, 1, 2, 1]);
+    }
+
+    #[test]
+    fn late_requester_gets_next_turn() {
+        assert_eq!(fair_queue(&[1, 1, 1, 1, 2]), vec![1, 2, 1, 1, 1]);
+        assert_eq!(fair_queue(&[1, 2, 1, 2, 3]), vec![1, 2, 3, 1, 2]);
+    }
+
+    #[test]
+    fn single_requester_keeps_order() {
+        assert_eq!(fair_queue(&[1, 1, 1]), vec![1, 1, 1]);
+    }
+
+    #[test]
+    fn looped_tracks_wait_for_their_turn() {
+        // The first track of requester 1 played and is appended again with the queue looping
+        let mut queue = vec![2, 2, 2, 2];
+        let index = fair_index(
+            queue.iter().map(|queued| Some(UserId::new(*queued))),
+            Some(UserId::new(1)),
+        );
+        queue.insert(index, 1);
+
+        assert_eq!(queue, vec![2, 1, 2, 2, 2]);
+    }
+
+    #[test]
+    fn previous_track_is_queued_once_when_looping_the_queue() {
+        let mut music = MusicManager::default();
+        music.set_loop(Loop::Queue);
+        let tracks = ["a", "b", "c"]
+            .map(|name| Track::new(HttpSource::new(format!("https://example.com/{}.mp3", name))));
+
+        block_on(async {
+            music.add_all(&tracks).await;
+            music.next().await;
+            music.previous().await;
+        });
+
+        let titles = music
+            .tracks()
+            .iter()
+            .map(|track| track.title.as_str())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            music.now_playing().map(|track| track.title.as_str()),
+            Some("a.mp3")
+        );
+        assert_eq!(titles, vec!["b.mp3", "c.mp3"]);
+    }
+}
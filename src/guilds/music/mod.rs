@@ -1,4 +1,12 @@
+pub mod cache;
 pub mod event;
+pub mod failures;
+pub mod filter;
 pub mod handler;
+pub mod idle;
+pub mod loudness;
 pub mod manager;
+pub mod persist;
+pub mod player;
+pub mod source;
 pub mod track;
@@ -0,0 +1,241 @@
+//! Saves the queue of every guild to the database so playback can continue after a restart
+
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{anyhow, Result};
+use serenity::{
+    all::{ChannelId, GuildId, UserId},
+    client::Context,
+    model::Timestamp,
+    prelude::{RwLock, TypeMap},
+};
+use songbird::SongbirdKey;
+
+use crate::{
+    guilds::data::GuildContext,
+    helper::music,
+    models::queue::{QueuedTrack, SavedQueue},
+    DatabaseKey, SourceKey,
+};
+
+use super::{
+    manager::{Loop, MusicManager},
+    source::{Metadata, SourceRegistry},
+    track::Track,
+};
+
+/// How often queues that changed or are playing get saved
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Periodically saves the queues in the background, so a crash loses at most a few seconds
+pub fn spawn_autosave(data: Arc<RwLock<TypeMap>>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(AUTOSAVE_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            if let Err(why) = save_all(&data, false).await {
+                println!("Failed to save queues: {:?}", why);
+            }
+        }
+    });
+}
+
+/// Saves the queue of every guild that changed or is still playing, or of every guild if `force` is set
+pub async fn save_all(data: &RwLock<TypeMap>, force: bool) -> Result<()> {
+    let (managers, database, songbird) = {
+        let typemap = data.read().await;
+
+        let managers = typemap
+            .get::<GuildContext>()
+            .expect("Expected GuildManager in TypeMap.")
+            .music_managers();
+
+        let database = typemap
+            .get::<DatabaseKey>()
+            .expect("Expected DatabaseKey in TypeMap.")
+            .clone();
+
+        let songbird = typemap
+            .get::<SongbirdKey>()
+            .expect("Expected SongbirdKey in TypeMap.")
+            .clone();
+
+        (managers, database, songbird)
+    };
+
+    for (guild, manager) in managers {
+        let mut music = manager.lock().await;
+        if !music.take_dirty() && music.now_playing().is_none() && !force {
+            continue;
+        }
+
+        let voice_channel = match songbird.get(guild) {
+            Some(call) => call.lock().await.current_channel(),
+            None => None,
+        };
+
+        let saved = match (voice_channel, music.text_channel()) {
+            (Some(voice_channel), Some(text_channel)) => {
+                snapshot(
+                    guild,
+                    &music,
+                    ChannelId::new(voice_channel.0.get()),
+                    text_channel,
+                )
+                .await
+            }
+            _ => None,
+        };
+
+        let mut connection = database.lock().await;
+        match saved {
+            Some((queue, tracks)) => queue.save(&mut connection, &tracks)?,
+            None => SavedQueue::delete(&mut connection, guild.get() as i64)?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Captures the current state of the music, `None` if there is nothing worth saving
+async fn snapshot(
+    guild: GuildId,
+    music: &MusicManager,
+    voice_channel: ChannelId,
+    text_channel: ChannelId,
+) -> Option<(SavedQueue, Vec<QueuedTrack>)> {
+    let playing = music.now_playing()?;
+    let position = music
+        .playback_state()
+        .await
+        .map(|(position, _)| position)
+        .unwrap_or_default();
+
+    let guild_id = guild.get() as i64;
+    let tracks = std::iter::once(playing)
+        .chain(music.tracks())
+        .enumerate()
+        .map(|(index, track)| QueuedTrack {
+            guild_id,
+            track_index: index as i32,
+            source: track.source.id(),
+            title: track.title.clone(),
+            author: track.author.clone(),
+            duration: track.duration.map(|duration| duration.as_secs() as i32),
+            url: track.url.clone(),
+            thumbnail: track.thumbnail.clone(),
+            requester: track.requester.map(|user| user.get() as i64),
+            enqueued_at: track.enqueued_at.unix_timestamp(),
+        })
+        .collect();
+
+    let queue = SavedQueue {
+        guild_id,
+        voice_channel: voice_channel.get() as i64,
+        text_channel: text_channel.get() as i64,
+        loop_mode: music.get_loop().to_string(),
+        volume: (music.volume() * 100.0).round() as i32,
+        position: position.as_millis() as i64,
+    };
+
+    Some((queue, tracks))
+}
+
+/// Rejoins the voice channels and continues playing every saved queue
+pub async fn restore_all(ctx: &Context) -> Result<()> {
+    let (database, sources) = {
+        let typemap = ctx.data.read().await;
+
+        let database = typemap
+            .get::<DatabaseKey>()
+            .expect("Expected DatabaseKey in TypeMap.")
+            .clone();
+
+        let sources = typemap
+            .get::<SourceKey>()
+            .expect("Expected SourceKey in TypeMap.")
+            .clone();
+
+        (database, sources)
+    };
+
+    let saved = {
+        let mut connection = database.lock().await;
+        SavedQueue::load_all(&mut connection)?
+    };
+
+    for (queue, tracks) in saved {
+        let guild = GuildId::new(queue.guild_id as u64);
+        if let Err(why) = restore(ctx, &sources, queue, tracks).await {
+            println!("Failed to restore the queue of {}: {:?}", guild, why);
+        }
+    }
+
+    Ok(())
+}
+
+async fn restore(
+    ctx: &Context,
+    sources: &SourceRegistry,
+    queue: SavedQueue,
+    tracks: Vec<QueuedTrack>,
+) -> Result<()> {
+    let tracks = tracks
+        .iter()
+        .filter_map(|track| restore_track(sources, track))
+        .collect::<Vec<_>>();
+
+    let guild = GuildId::new(queue.guild_id as u64);
+    if tracks.is_empty() {
+        return Ok(());
+    }
+
+    let songbird = songbird::get(ctx)
+        .await
+        .ok_or_else(|| anyhow!("Songbird was not registered"))?;
+
+    let manager = music::get_manager(ctx, guild).await;
+    music::connect_to(
+        songbird,
+        Arc::clone(&manager),
+        guild,
+        ChannelId::new(queue.voice_channel as u64),
+    )
+    .await?;
+
+    let mut music = manager.lock().await;
+    let text_channel = ChannelId::new(queue.text_channel as u64);
+    music::attach_handler(ctx, &mut music, guild, text_channel).await?;
+
+    music
+        .restore(
+            tracks,
+            queue.loop_mode.parse().unwrap_or(Loop::None),
+            queue.volume as f32 / 100.0,
+            Duration::from_millis(queue.position as u64),
+        )
+        .await;
+
+    Ok(())
+}
+
+fn restore_track(sources: &SourceRegistry, saved: &QueuedTrack) -> Option<Track> {
+    let metadata = Metadata {
+        title: saved.title.clone(),
+        author: saved.author.clone(),
+        duration: saved
+            .duration
+            .map(|seconds| Duration::from_secs(seconds as u64)),
+        thumbnail: saved.thumbnail.clone(),
+    };
+
+    let mut track = sources.restore(&saved.source, &metadata)?;
+    track.requester = saved.requester.map(|user| UserId::new(user as u64));
+    if let Ok(enqueued_at) = Timestamp::from_unix_timestamp(saved.enqueued_at) {
+        track.enqueued_at = enqueued_at;
+    }
+
+    Some(track)
+}
@@ -0,0 +1,263 @@
+//! The player shows what is playing and lets listeners control the music through its buttons.
+//! Every guild has a single player message that is edited in place while the music changes.
+
+use std::time::Duration;
+
+use anyhow::Result;
+use serenity::{
+    all::{ButtonStyle, ChannelId, GuildId, MessageId},
+    builder::{CreateActionRow, CreateButton, CreateEmbed, CreateMessage, EditMessage},
+    client::Context,
+    prelude::Mentionable,
+};
+use tokio::sync::OnceCell;
+
+use crate::helper::{embed, emoji, helper::format_duration, music};
+
+use super::{manager::Loop, track::Track};
+
+/// How often the progress bar is updated while a track plays
+pub const UPDATE_INTERVAL: Duration = Duration::from_secs(15);
+/// How many segments the progress bar has
+const PROGRESS_BAR_LENGTH: usize = 16;
+
+/// Every button of the player has a custom id starting with this, so presses can be told apart
+/// from other components
+pub const PREFIX: &str = "player:";
+pub const PAUSE: &str = "player:pause";
+pub const RESUME: &str = "player:resume";
+pub const SKIP: &str = "player:skip";
+pub const PREVIOUS: &str = "player:previous";
+pub const LOOP: &str = "player:loop";
+pub const SHUFFLE: &str = "player:shuffle";
+pub const STOP: &str = "player:stop";
+
+/// Updates the player of the guild in the background. It waits until the music manager is unlocked,
+/// so it can be called while the manager is locked, such as from the music event handler.
+pub fn refresh(ctx: &Context, guild: GuildId) {
+    let ctx = ctx.clone();
+    tokio::spawn(async move {
+        if let Err(why) = update(&ctx, guild).await {
+            println!("Failed to update the player: {:?}", why);
+        }
+    });
+}
+
+/// Shows the state of the music in the player, sending a new one when there is none yet or it was
+/// deleted. Once nothing is playing the player is left behind, the next track gets a new one.
+/// The music manager is only locked to read its state and to remember the player, so it isn't kept
+/// waiting on Discord.
+async fn update(ctx: &Context, guild: GuildId) -> Result<()> {
+    let manager = music::get_manager(ctx, guild).await;
+    let state = {
+        let mut music = manager.lock().await;
+        let Some(track) = music.now_playing().cloned() else {
+            let player = music.take_player_message();
+            drop(music);
+
+            if let Some((channel, message)) = player {
+                let edit = EditMessage::new()
+                    .embed(embed::build("Nothing is playing right now"))
+                    .components(Vec::new());
+                channel.edit_message(&ctx.http, message, edit).await?;
+            }
+
+            return Ok(());
+        };
+
+        let Some(channel) = music.text_channel() else {
+            return Ok(());
+        };
+
+        let (position, paused) = music.playback_state().await.unwrap_or_default();
+        State {
+            track,
+            up_next: music.get(0).cloned(),
+            position,
+            paused,
+            music_loop: *music.get_loop(),
+            volume: music.volume(),
+            channel,
+            message: music.player_message(),
+        }
+    };
+
+    let embed = build(ctx, &state).await;
+    let components = buttons(state.paused, state.music_loop);
+
+    if let Some((channel, message)) = state.message {
+        let edit = EditMessage::new()
+            .embed(embed.clone())
+            .components(components.clone());
+
+        // The player can't be edited when it was deleted, a new one is sent instead
+        if channel.edit_message(&ctx.http, message, edit).await.is_ok() {
+            return Ok(());
+        }
+    }
+
+    let message = CreateMessage::new().embed(embed).components(components);
+    let message = state.channel.send_message(&ctx.http, message).await?;
+
+    let mut music = manager.lock().await;
+    if music.player_message() != state.message {
+        // Another update sent a player in the meantime, the guild only gets one
+        drop(music);
+        message.delete(&ctx.http).await?;
+        return Ok(());
+    }
+
+    music.set_player_message(state.channel, message.id);
+
+    Ok(())
+}
+
+/// What the player shows, copied from the music manager so it doesn't stay locked
+struct State {
+    track: Track,
+    up_next: Option<Track>,
+    position: Duration,
+    paused: bool,
+    music_loop: Loop,
+    volume: f32,
+    /// Where a new player is sent
+    channel: ChannelId,
+    /// The current player, if there is one
+    message: Option<(ChannelId, MessageId)>,
+}
+
+/// The emote in front of the track, fetched once since it doesn't change. The player is still shown
+/// with a plain emoji when it can't be fetched.
+async fn music_emote(ctx: &Context) -> &'static str {
+    static EMOTE: OnceCell<String> = OnceCell::const_new();
+
+    match EMOTE
+        .get_or_try_init(|| emoji::get_bot_emote(ctx, "p_music"))
+        .await
+    {
+        Ok(emote) => emote.as_str(),
+        Err(why) => {
+            println!("Failed to fetch the music emote: {:?}", why);
+            "🎵"
+        }
+    }
+}
+
+async fn build(ctx: &Context, state: &State) -> CreateEmbed {
+    let track = &state.track;
+    let mut description = format!(
+        "{} Now playing {}",
+        music_emote(ctx).await,
+        track.linked_title()
+    );
+
+    if let Some(requester) = track.requester {
+        description.push_str(&format!("\nRequested by {}", requester.mention()));
+    }
+
+    let up_next = match &state.up_next {
+        Some(next) => next.linked_title(),
+        None => "nothing".to_string(),
+    };
+
+    description.push_str(&format!(
+        "\n\n{} {}\nLoop: **{}** | Volume: **{}%** | Up next: {}",
+        if state.paused { "⏸" } else { "▶" },
+        progress_bar(state.position, track.duration),
+        state.music_loop,
+        (state.volume * 100.0).round(),
+        up_next
+    ));
+
+    let embed = embed::build(description);
+    match &track.thumbnail {
+        Some(thumbnail) => embed.thumbnail(thumbnail),
+        None => embed,
+    }
+}
+
+/// How far along the track is, just the position when the length is unknown such as for streams
+fn progress_bar(position: Duration, duration: Option<Duration>) -> String {
+    let Some(duration) = duration.filter(|duration| !duration.is_zero()) else {
+        return format!("`{}`", format_duration(position));
+    };
+
+    let progress = (position.as_secs_f64() / duration.as_secs_f64()).clamp(0.0, 1.0);
+    let knob = ((progress * PROGRESS_BAR_LENGTH as f64) as usize).min(PROGRESS_BAR_LENGTH - 1);
+    let bar = (0..PROGRESS_BAR_LENGTH)
+        .map(|segment| if segment == knob { "🔘" } else { "▬" })
+        .collect::<String>();
+
+    format!(
+        "`{}` {} `{}`",
+        format_duration(position),
+        bar,
+        format_duration(duration)
+    )
+}
+
+fn buttons(paused: bool, music_loop: Loop) -> Vec<CreateActionRow> {
+    let pause = if paused {
+        CreateButton::new(RESUME)
+            .emoji('▶')
+            .label("Resume")
+            .style(ButtonStyle::Success)
+    } else {
+        CreateButton::new(PAUSE)
+            .emoji('⏸')
+            .label("Pause")
+            .style(ButtonStyle::Secondary)
+    };
+
+    let loop_style = match music_loop {
+        Loop::None => ButtonStyle::Secondary,
+        _ => ButtonStyle::Primary,
+    };
+
+    vec![
+        CreateActionRow::Buttons(vec![
+            CreateButton::new(PREVIOUS)
+                .emoji('⏮')
+                .label("Previous")
+                .style(ButtonStyle::Secondary),
+            pause,
+            CreateButton::new(SKIP)
+                .emoji('⏭')
+                .label("Skip")
+                .style(ButtonStyle::Secondary),
+            CreateButton::new(STOP)
+                .emoji('⏹')
+                .label("Stop")
+                .style(ButtonStyle::Danger),
+        ]),
+        CreateActionRow::Buttons(vec![
+            CreateButton::new(LOOP)
+                .emoji('🔁')
+                .label(format!("Loop: {}", music_loop))
+                .style(loop_style),
+            CreateButton::new(SHUFFLE)
+                .emoji('🔀')
+                .label("Shuffle")
+                .style(ButtonStyle::Secondary),
+        ]),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn progress_bar_moves_along_the_track() {
+        let duration = Some(Duration::from_secs(160));
+
+        assert!(progress_bar(Duration::ZERO, duration).starts_with("`0:00` 🔘▬"));
+        assert!(progress_bar(Duration::from_secs(80), duration).contains("▬🔘▬"));
+        assert!(progress_bar(Duration::from_secs(160), duration).ends_with("▬🔘 `2:40`"));
+    }
+
+    #[test]
+    fn progress_bar_without_duration_shows_position() {
+        assert_eq!(progress_bar(Duration::from_secs(75), None), "`1:15`");
+    }
+}
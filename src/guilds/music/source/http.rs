@@ -0,0 +1,75 @@
+//! Plays audio files straight from a link
+
+use anyhow::Result;
+use async_trait::async_trait;
+use reqwest::Client;
+use songbird::input::{HttpRequest, Input};
+
+use crate::guilds::music::track::Track;
+
+use super::{Metadata, Resolved, Source, SourceProvider};
+
+pub struct HttpProvider;
+
+#[async_trait]
+impl SourceProvider for HttpProvider {
+    fn can_resolve(&self, query: &str) -> bool {
+        query.starts_with("http://") || query.starts_with("https://")
+    }
+
+    async fn resolve(&self, query: &str) -> Result<Resolved> {
+        Ok(Resolved::Direct(vec![Track::new(HttpSource::new(query))]))
+    }
+
+    fn restore(&self, id: &str, _metadata: &Metadata) -> Option<Track> {
+        let url = id.strip_prefix("http:")?;
+        Some(Track::new(HttpSource::new(url)))
+    }
+}
+
+pub struct HttpSource {
+    url: String,
+}
+
+impl HttpSource {
+    pub fn new(url: impl Into<String>) -> Self {
+        HttpSource { url: url.into() }
+    }
+
+    /// Uses the file name as the title, since there's nothing better to go on
+    fn file_name(&self) -> String {
+        let path = self.url.split(['?', '#']).next().unwrap_or(&self.url);
+
+        path.rsplit('/')
+            .find(|segment| !segment.is_empty())
+            .unwrap_or(&self.url)
+            .to_string()
+    }
+}
+
+#[async_trait]
+impl Source for HttpSource {
+    fn id(&self) -> String {
+        format!("http:{}", self.url)
+    }
+
+    fn url(&self) -> Option<String> {
+        Some(self.url.clone())
+    }
+
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            title: self.file_name(),
+            ..Default::default()
+        }
+    }
+
+    async fn input(&self, client: &Client) -> Result<Input> {
+        Ok(HttpRequest::new(client.clone(), self.url.clone()).into())
+    }
+
+    /// Links are often radio streams, which never end
+    fn seekable(&self) -> bool {
+        false
+    }
+}
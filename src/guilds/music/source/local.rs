@@ -0,0 +1,238 @@
+//! Plays audio files from the configured music library on disk
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use diesel::SqliteConnection;
+use reqwest::Client;
+use songbird::input::{File, Input};
+use symphonia::core::{
+    formats::FormatOptions,
+    io::MediaSourceStream,
+    meta::{MetadataOptions, MetadataRevision, StandardTagKey},
+    probe::Hint,
+};
+use tokio::sync::Mutex;
+
+use crate::{guilds::music::track::Track, models::library::LibraryEntry};
+
+use super::{Metadata, Resolved, Source, SourceProvider};
+
+const PREFIX: &str = "local:";
+const EXTENSIONS: [&str; 7] = ["mp3", "m4a", "mp4", "aac", "flac", "ogg", "wav"];
+/// How many results a search returns at most
+const SEARCH_LIMIT: i64 = 25;
+/// How many files are read before they're saved to the database at once while scanning
+const SCAN_BATCH_SIZE: usize = 50;
+
+pub struct LocalProvider {
+    database: Arc<Mutex<SqliteConnection>>,
+}
+
+impl LocalProvider {
+    pub fn new(database: Arc<Mutex<SqliteConnection>>) -> Self {
+        LocalProvider { database }
+    }
+}
+
+#[async_trait]
+impl SourceProvider for LocalProvider {
+    fn can_resolve(&self, query: &str) -> bool {
+        query.starts_with(PREFIX)
+    }
+
+    async fn resolve(&self, query: &str) -> Result<Resolved> {
+        let query = query.trim_start_matches(PREFIX).trim();
+
+        let mut connection = self.database.lock().await;
+        let entries = LibraryEntry::search(&mut connection, query, SEARCH_LIMIT)?;
+
+        Ok(Resolved::Search(
+            entries.into_iter().map(LocalSource::track).collect(),
+        ))
+    }
+
+    fn restore(&self, id: &str, metadata: &Metadata) -> Option<Track> {
+        let path = id.strip_prefix(PREFIX)?;
+
+        Some(LocalSource::track(LibraryEntry {
+            path: path.to_string(),
+            title: metadata.title.clone(),
+            artist: metadata.author.clone(),
+            album: None,
+            duration: metadata.duration.map(|duration| duration.as_secs() as i32),
+            modified: 0,
+        }))
+    }
+}
+
+pub struct LocalSource {
+    entry: LibraryEntry,
+}
+
+impl LocalSource {
+    pub fn track(entry: LibraryEntry) -> Track {
+        Track::new(LocalSource { entry })
+    }
+}
+
+#[async_trait]
+impl Source for LocalSource {
+    fn id(&self) -> String {
+        format!("local:{}", self.entry.path)
+    }
+
+    fn url(&self) -> Option<String> {
+        None
+    }
+
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            title: self.entry.title.clone(),
+            author: self.entry.artist.clone(),
+            duration: self
+                .entry
+                .duration
+                .map(|seconds| Duration::from_secs(seconds as u64)),
+            thumbnail: None,
+        }
+    }
+
+    async fn input(&self, _client: &Client) -> Result<Input> {
+        Ok(File::new(PathBuf::from(&self.entry.path)).into())
+    }
+}
+
+/// Indexes every audio file in the directory, files that haven't changed since the last scan are skipped
+/// and files that no longer exist are removed from the index. This blocks, so it should be run on a
+/// blocking thread. Tags are read without holding the database, which is only locked to save each
+/// batch of files, so the rest of the bot isn't kept waiting on large libraries.
+pub fn scan(database: &Mutex<SqliteConnection>, directory: &Path) -> Result<usize> {
+    let mut indexed = LibraryEntry::indexed(&mut database.blocking_lock())?
+        .into_iter()
+        .collect::<HashMap<String, i64>>();
+
+    let mut files = Vec::new();
+    find_files(directory, &mut files)?;
+
+    let mut updated = 0;
+    for batch in files.chunks(SCAN_BATCH_SIZE) {
+        let mut entries = Vec::new();
+        for file in batch {
+            let path = file.to_string_lossy().to_string();
+            let modified = fs::metadata(file)?
+                .modified()?
+                .duration_since(UNIX_EPOCH)?
+                .as_secs() as i64;
+
+            if indexed.remove(&path) == Some(modified) {
+                continue;
+            }
+
+            match read_entry(file, path, modified) {
+                Ok(entry) => entries.push(entry),
+                Err(e) => println!("Unable to read tags of {:?}: {:?}", file, e),
+            }
+        }
+
+        let mut connection = database.blocking_lock();
+        for entry in &entries {
+            entry.save(&mut connection)?;
+        }
+
+        updated += entries.len();
+    }
+
+    // Whatever is left over wasn't found on disk anymore
+    let mut connection = database.blocking_lock();
+    for path in indexed.keys() {
+        LibraryEntry::delete(&mut connection, path)?;
+    }
+
+    Ok(updated)
+}
+
+fn find_files(directory: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+
+        if path.is_dir() {
+            find_files(&path, files)?;
+        } else if path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| EXTENSIONS.contains(&extension.to_lowercase().as_str()))
+        {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads the tags and duration of an audio file with symphonia
+fn read_entry(file: &Path, path: String, modified: i64) -> Result<LibraryEntry> {
+    let stream = MediaSourceStream::new(Box::new(fs::File::open(file)?), Default::default());
+
+    let mut hint = Hint::new();
+    if let Some(extension) = file.extension().and_then(|extension| extension.to_str()) {
+        hint.with_extension(extension);
+    }
+
+    let mut probed = symphonia::default::get_probe().format(
+        &hint,
+        stream,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    )?;
+
+    let mut entry = LibraryEntry {
+        title: file
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| path.clone()),
+        path,
+        artist: None,
+        album: None,
+        duration: None,
+        modified,
+    };
+
+    // Tags can either be in front of the container (such as ID3) or inside of it
+    if let Some(metadata) = probed.metadata.get() {
+        if let Some(revision) = metadata.current() {
+            read_tags(revision, &mut entry);
+        }
+    }
+
+    if let Some(revision) = probed.format.metadata().current() {
+        read_tags(revision, &mut entry);
+    }
+
+    if let Some(track) = probed.format.default_track() {
+        let params = &track.codec_params;
+        if let (Some(frames), Some(time_base)) = (params.n_frames, params.time_base) {
+            entry.duration = Some(time_base.calc_time(frames).seconds as i32);
+        }
+    }
+
+    Ok(entry)
+}
+
+fn read_tags(revision: &MetadataRevision, entry: &mut LibraryEntry) {
+    for tag in revision.tags() {
+        match tag.std_key {
+            Some(StandardTagKey::TrackTitle) => entry.title = tag.value.to_string(),
+            Some(StandardTagKey::Artist) => entry.artist = Some(tag.value.to_string()),
+            Some(StandardTagKey::Album) => entry.album = Some(tag.value.to_string()),
+            _ => {}
+        }
+    }
+}
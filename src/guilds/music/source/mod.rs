@@ -0,0 +1,150 @@
+//! Sources are where tracks come from, every provider knows how to turn a query into tracks
+//! and every source knows how to turn itself into something songbird can play.
+
+use std::{path::Path, time::Duration};
+
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use reqwest::Client;
+use songbird::input::Input;
+
+use super::track::Track;
+
+pub mod http;
+pub mod local;
+pub mod youtube;
+
+/// A single playable resource
+#[async_trait]
+pub trait Source: Send + Sync {
+    /// Uniquely identifies the resource, such as `youtube:dQw4w9WgXcQ`
+    fn id(&self) -> String;
+    /// A link to the resource that can be shown to users, if there is one
+    fn url(&self) -> Option<String>;
+    fn metadata(&self) -> Metadata;
+    /// Where playback should start instead of the beginning
+    fn start(&self) -> Option<Duration> {
+        None
+    }
+    /// Creates the input songbird will play
+    async fn input(&self, client: &Client) -> Result<Input>;
+    /// Whether the audio has a fixed length that can be seeked through, which live streams don't
+    fn seekable(&self) -> bool {
+        true
+    }
+    /// Whether the audio can be downloaded ahead of time and kept in the cache
+    fn cacheable(&self) -> bool {
+        false
+    }
+    /// Downloads the audio to the path, only called when the source is cacheable
+    async fn download(&self, _path: &Path) -> Result<()> {
+        bail!("{} can't be downloaded", self.id())
+    }
+}
+
+/// Turns queries into tracks for a single kind of source
+#[async_trait]
+pub trait SourceProvider: Send + Sync {
+    /// Check if the provider recognises the query, such as a link to its website
+    fn can_resolve(&self, query: &str) -> bool;
+    async fn resolve(&self, query: &str) -> Result<Resolved>;
+    /// Recreates a track from a source id and its metadata, such as when loading a saved queue,
+    /// `None` if the id doesn't belong to this provider
+    fn restore(&self, id: &str, metadata: &Metadata) -> Option<Track>;
+    /// Tracks similar to the source, empty if the id doesn't belong to this provider
+    async fn related(&self, _id: &str) -> Result<Vec<Track>> {
+        Ok(Vec::new())
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Metadata {
+    pub title: String,
+    pub author: Option<String>,
+    pub duration: Option<Duration>,
+    pub thumbnail: Option<String>,
+}
+
+pub enum Resolved {
+    /// Results of a search, the user may pick one of them
+    Search(Vec<Track>),
+    /// Tracks that were requested directly, such as through a link
+    Direct(Vec<Track>),
+}
+
+impl Resolved {
+    pub fn tracks(&self) -> &[Track] {
+        match self {
+            Resolved::Search(tracks) | Resolved::Direct(tracks) => tracks,
+        }
+    }
+}
+
+/// Holds every provider, queries go to the first provider that recognises them
+pub struct SourceRegistry {
+    providers: Vec<Box<dyn SourceProvider>>,
+}
+
+impl SourceRegistry {
+    /// The default provider is used to search for queries no other provider recognises
+    pub fn new(default: impl SourceProvider + 'static) -> Self {
+        SourceRegistry {
+            providers: vec![Box::new(default)],
+        }
+    }
+
+    pub fn register(mut self, provider: impl SourceProvider + 'static) -> Self {
+        self.providers.push(Box::new(provider));
+        self
+    }
+
+    pub async fn resolve(&self, query: &str) -> Result<Resolved> {
+        let provider = self
+            .providers
+            .iter()
+            .find(|provider| provider.can_resolve(query))
+            .unwrap_or(&self.providers[0]);
+
+        provider.resolve(query).await
+    }
+
+    /// Results of searching for the query, empty when a provider recognises it as something else,
+    /// such as a link, so nothing is fetched while it's being typed
+    pub async fn search(&self, query: &str) -> Result<Vec<Track>> {
+        if self
+            .providers
+            .iter()
+            .any(|provider| provider.can_resolve(query))
+        {
+            return Ok(Vec::new());
+        }
+
+        match self.providers[0].resolve(query).await? {
+            Resolved::Search(tracks) => Ok(tracks),
+            Resolved::Direct(_) => Ok(Vec::new()),
+        }
+    }
+
+    pub fn restore(&self, id: &str, metadata: &Metadata) -> Option<Track> {
+        self.providers
+            .iter()
+            .find_map(|provider| provider.restore(id, metadata))
+    }
+
+    /// Tracks similar to the source, from the provider it belongs to
+    pub async fn related(&self, id: &str) -> Result<Vec<Track>> {
+        for provider in &self.providers {
+            let tracks = provider.related(id).await?;
+            if !tracks.is_empty() {
+                return Ok(tracks);
+            }
+        }
+
+        Ok(Vec::new())
+    }
+}
+
+/// The kind of source a source id belongs to, such as `youtube` for `youtube:dQw4w9WgXcQ`
+pub fn kind(id: &str) -> &str {
+    id.split_once(':').map_or(id, |(kind, _)| kind)
+}
@@ -0,0 +1,270 @@
+use std::{path::Path, time::Duration};
+
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use invidious::{
+    hidden::{PlaylistItem, RecommendedVideo, SearchItem},
+    ClientAsync, ClientAsyncTrait, CommonVideo,
+};
+use reqwest::Client;
+use songbird::input::{Input, YoutubeDl};
+use tokio::process::Command;
+
+use crate::guilds::music::track::Track;
+
+use super::{Metadata, Resolved, Source, SourceProvider};
+
+pub mod url;
+
+use self::url::YoutubeUrl;
+
+pub struct YoutubeProvider {
+    client: ClientAsync,
+    playlist_limit: usize,
+}
+
+impl YoutubeProvider {
+    /// At most `playlist_limit` tracks are imported from a single playlist
+    pub fn new(client: ClientAsync, playlist_limit: usize) -> Self {
+        YoutubeProvider {
+            client,
+            playlist_limit,
+        }
+    }
+
+    /// Fetches every page of the playlist until there are no more videos or the limit is reached
+    async fn playlist(&self, id: &str) -> Result<Vec<Track>> {
+        let mut tracks: Vec<Track> = Vec::new();
+        let mut page = 1;
+
+        while tracks.len() < self.playlist_limit {
+            let playlist = self
+                .client
+                .playlist(id, Some(&format!("page={}", page)))
+                .await
+                .map_err(|e| anyhow!("Failed to find playlist: {:?}", e))?;
+
+            let found = tracks.len();
+            for video in playlist.videos {
+                let track = YoutubeSource::from_playlist_item(video);
+                if !tracks.contains(&track) {
+                    tracks.push(track);
+                }
+            }
+
+            // Some instances ignore the page and keep returning the same videos
+            if tracks.len() == found {
+                break;
+            }
+
+            page += 1;
+        }
+
+        tracks.truncate(self.playlist_limit);
+        Ok(tracks)
+    }
+}
+
+#[async_trait]
+impl SourceProvider for YoutubeProvider {
+    fn can_resolve(&self, query: &str) -> bool {
+        YoutubeUrl::parse(query).is_some()
+    }
+
+    async fn resolve(&self, query: &str) -> Result<Resolved> {
+        let Some(url) = YoutubeUrl::parse(query) else {
+            let videos = query_youtube(&self.client, query).await?;
+            return Ok(Resolved::Search(
+                videos.into_iter().map(YoutubeSource::track).collect(),
+            ));
+        };
+
+        // Mixes are generated for each user, so they can't be fetched as a playlist
+        let is_mix = url
+            .playlist
+            .as_ref()
+            .is_some_and(|playlist| playlist.starts_with("RD"));
+
+        let video = match (url.video, url.playlist) {
+            (Some(video), Some(_)) if is_mix => video,
+            (_, Some(playlist)) => return Ok(Resolved::Direct(self.playlist(&playlist).await?)),
+            (Some(video), None) => video,
+            (None, None) => unreachable!("a parsed url always has a video or playlist"),
+        };
+
+        let video: CommonVideo = self
+            .client
+            .video(&video, None)
+            .await
+            .map_err(|e| anyhow!("Failed to find video: {:?}", e))?
+            .into();
+
+        let mut source = YoutubeSource::from_video(video);
+        source.start = url.start;
+
+        Ok(Resolved::Direct(vec![Track::new(source)]))
+    }
+
+    fn restore(&self, id: &str, metadata: &Metadata) -> Option<Track> {
+        let id = id.strip_prefix("youtube:")?;
+
+        Some(Track::new(YoutubeSource {
+            id: id.to_string(),
+            title: metadata.title.clone(),
+            author: metadata.author.clone().unwrap_or_default(),
+            duration: metadata.duration.unwrap_or_default(),
+            thumbnail: metadata.thumbnail.clone(),
+            start: None,
+        }))
+    }
+
+    async fn related(&self, id: &str) -> Result<Vec<Track>> {
+        let Some(id) = id.strip_prefix("youtube:") else {
+            return Ok(Vec::new());
+        };
+
+        let video = self
+            .client
+            .video(id, None)
+            .await
+            .map_err(|e| anyhow!("Failed to find related videos: {:?}", e))?;
+
+        Ok(video
+            .recommended_videos
+            .into_iter()
+            .map(YoutubeSource::from_recommended)
+            .collect())
+    }
+}
+
+pub struct YoutubeSource {
+    id: String,
+    title: String,
+    author: String,
+    duration: Duration,
+    thumbnail: Option<String>,
+    start: Option<Duration>,
+}
+
+impl YoutubeSource {
+    pub fn track(video: CommonVideo) -> Track {
+        Track::new(YoutubeSource::from_video(video))
+    }
+
+    fn from_video(video: CommonVideo) -> Self {
+        YoutubeSource {
+            thumbnail: video.thumbnails.first().map(|t| t.url.clone()),
+            duration: Duration::from_secs(video.length as u64),
+            id: video.id,
+            title: video.title,
+            author: video.author,
+            start: None,
+        }
+    }
+
+    fn from_playlist_item(video: PlaylistItem) -> Track {
+        Track::new(YoutubeSource {
+            thumbnail: video.thumbnails.first().map(|t| t.url.clone()),
+            duration: Duration::from_secs(video.length as u64),
+            id: video.id,
+            title: video.title,
+            author: video.author,
+            start: None,
+        })
+    }
+
+    fn from_recommended(video: RecommendedVideo) -> Track {
+        Track::new(YoutubeSource {
+            thumbnail: video.thumbnails.first().map(|t| t.url.clone()),
+            duration: Duration::from_secs(video.length as u64),
+            id: video.id,
+            title: video.title,
+            author: video.author,
+            start: None,
+        })
+    }
+
+    fn watch_url(&self) -> String {
+        format!("https://www.youtube.com/watch?v={}", self.id)
+    }
+}
+
+#[async_trait]
+impl Source for YoutubeSource {
+    fn id(&self) -> String {
+        format!("youtube:{}", self.id)
+    }
+
+    fn url(&self) -> Option<String> {
+        match self.start {
+            Some(start) => Some(format!("{}&t={}s", self.watch_url(), start.as_secs())),
+            None => Some(self.watch_url()),
+        }
+    }
+
+    fn start(&self) -> Option<Duration> {
+        self.start
+    }
+
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            title: self.title.clone(),
+            author: Some(self.author.clone()),
+            duration: Some(self.duration),
+            thumbnail: self.thumbnail.clone(),
+        }
+    }
+
+    async fn input(&self, client: &Client) -> Result<Input> {
+        Ok(YoutubeDl::new(client.clone(), self.watch_url()).into())
+    }
+
+    /// Live streams have no length
+    fn seekable(&self) -> bool {
+        !self.duration.is_zero()
+    }
+
+    /// Live streams have no length and never finish downloading
+    fn cacheable(&self) -> bool {
+        !self.duration.is_zero()
+    }
+
+    async fn download(&self, path: &Path) -> Result<()> {
+        // Prefer m4a, since symphonia can't read webm
+        let status = Command::new("yt-dlp")
+            .args([
+                "-f",
+                "bestaudio[ext=m4a]/bestaudio",
+                "--no-playlist",
+                "-q",
+                "-o",
+            ])
+            .arg(path)
+            .arg(self.watch_url())
+            .status()
+            .await?;
+
+        if !status.success() {
+            bail!("yt-dlp exited with {}", status);
+        }
+
+        Ok(())
+    }
+}
+
+/// Query youtube for videos that match the query
+pub async fn query_youtube(client: &ClientAsync, query: &str) -> Result<Vec<CommonVideo>> {
+    let search = client
+        .search(Some(&format!("q={}", query)))
+        .await
+        .map_err(|e| anyhow!("Failed to search for tracks: {:?}", e))?;
+
+    Ok(search.items.iter().filter_map(is_video).collect())
+}
+
+fn is_video(item: &SearchItem) -> Option<CommonVideo> {
+    return match item {
+        SearchItem::Video(v) => Some(v.clone()),
+        _ => None,
+    };
+}
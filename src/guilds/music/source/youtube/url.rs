@@ -0,0 +1,243 @@
+//! Pulls the video, playlist and start offset out of the many shapes a YouTube link can take
+
+use std::time::Duration;
+
+use reqwest::Url;
+
+const HOSTS: [&str; 6] = [
+    "youtube.com",
+    "www.youtube.com",
+    "m.youtube.com",
+    "music.youtube.com",
+    "youtube-nocookie.com",
+    "www.youtube-nocookie.com",
+];
+
+#[derive(Debug, Default, PartialEq)]
+pub struct YoutubeUrl {
+    pub video: Option<String>,
+    pub playlist: Option<String>,
+    /// Where playback should start, from `t=` or `start=`
+    pub start: Option<Duration>,
+}
+
+impl YoutubeUrl {
+    /// Parses a link to a YouTube video or playlist, returns `None` if it isn't one
+    pub fn parse(link: &str) -> Option<YoutubeUrl> {
+        let url = Url::parse(link.trim()).ok()?;
+        if url.scheme() != "http" && url.scheme() != "https" {
+            return None;
+        }
+
+        let host = url.host_str()?.to_lowercase();
+        let segments = url
+            .path_segments()
+            .map(|segments| segments.filter(|s| !s.is_empty()).collect::<Vec<_>>())
+            .unwrap_or_default();
+
+        let mut parsed = YoutubeUrl::default();
+        for (key, value) in url.query_pairs() {
+            match key.as_ref() {
+                "v" => parsed.video = Some(value.to_string()),
+                "list" => parsed.playlist = Some(value.to_string()),
+                "t" | "start" => parsed.start = parse_offset(&value),
+                _ => {}
+            }
+        }
+
+        // Embedded players put the offset in the fragment instead, e.g. `#t=42`
+        if let Some(offset) = url.fragment().and_then(|f| f.strip_prefix("t=")) {
+            parsed.start = parse_offset(offset);
+        }
+
+        if host == "youtu.be" || host == "www.youtu.be" {
+            parsed.video = segments.first().map(|id| id.to_string());
+        } else if HOSTS.contains(&host.as_str()) {
+            if let ["shorts" | "embed" | "live" | "v", id, ..] = segments.as_slice() {
+                parsed.video = Some(id.to_string());
+            }
+        } else {
+            return None;
+        }
+
+        parsed.video = parsed.video.filter(|id| is_video_id(id));
+        parsed.playlist = parsed.playlist.filter(|id| !id.is_empty());
+
+        if parsed.video.is_none() && parsed.playlist.is_none() {
+            return None;
+        }
+
+        Some(parsed)
+    }
+}
+
+/// Video ids are always 11 characters of base64 (url safe)
+fn is_video_id(id: &str) -> bool {
+    id.len() == 11
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Parses an offset such as `42`, `42s` or `1h2m3s`
+fn parse_offset(offset: &str) -> Option<Duration> {
+    if let Ok(seconds) = offset.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let mut total = 0;
+    let mut number = String::new();
+    for c in offset.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+
+        let value = number.parse::<u64>().ok()?;
+        number.clear();
+
+        total += match c {
+            'h' => value * 3600,
+            'm' => value * 60,
+            's' => value,
+            _ => return None,
+        };
+    }
+
+    if !number.is_empty() {
+        return None;
+    }
+
+    Some(Duration::from_secs(total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn video(id: &str) -> YoutubeUrl {
+        YoutubeUrl {
+            video: Some(id.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn watch() {
+        assert_eq!(
+            YoutubeUrl::parse("https://www.youtube.com/watch?v=dQw4w9WgXcQ"),
+            Some(video("dQw4w9WgXcQ"))
+        );
+        assert_eq!(
+            YoutubeUrl::parse("https://m.youtube.com/watch?feature=share&v=dQw4w9WgXcQ"),
+            Some(video("dQw4w9WgXcQ"))
+        );
+        assert_eq!(
+            YoutubeUrl::parse("http://youtube.com/watch?v=dQw4w9WgXcQ"),
+            Some(video("dQw4w9WgXcQ"))
+        );
+    }
+
+    #[test]
+    fn short_links() {
+        assert_eq!(
+            YoutubeUrl::parse("https://youtu.be/dQw4w9WgXcQ"),
+            Some(video("dQw4w9WgXcQ"))
+        );
+        assert_eq!(
+            YoutubeUrl::parse("https://youtu.be/dQw4w9WgXcQ?si=abcdef"),
+            Some(video("dQw4w9WgXcQ"))
+        );
+    }
+
+    #[test]
+    fn shorts_embeds_and_live() {
+        assert_eq!(
+            YoutubeUrl::parse("https://www.youtube.com/shorts/dQw4w9WgXcQ"),
+            Some(video("dQw4w9WgXcQ"))
+        );
+        assert_eq!(
+            YoutubeUrl::parse("https://www.youtube-nocookie.com/embed/dQw4w9WgXcQ"),
+            Some(video("dQw4w9WgXcQ"))
+        );
+        assert_eq!(
+            YoutubeUrl::parse("https://www.youtube.com/live/dQw4w9WgXcQ?feature=shared"),
+            Some(video("dQw4w9WgXcQ"))
+        );
+    }
+
+    #[test]
+    fn music() {
+        assert_eq!(
+            YoutubeUrl::parse("https://music.youtube.com/watch?v=dQw4w9WgXcQ&feature=share"),
+            Some(video("dQw4w9WgXcQ"))
+        );
+    }
+
+    #[test]
+    fn timestamps() {
+        let expected = |seconds| YoutubeUrl {
+            start: Some(Duration::from_secs(seconds)),
+            ..video("dQw4w9WgXcQ")
+        };
+
+        assert_eq!(
+            YoutubeUrl::parse("https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=42s"),
+            Some(expected(42))
+        );
+        assert_eq!(
+            YoutubeUrl::parse("https://youtu.be/dQw4w9WgXcQ?t=42"),
+            Some(expected(42))
+        );
+        assert_eq!(
+            YoutubeUrl::parse("https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=1h2m3s"),
+            Some(expected(3723))
+        );
+        assert_eq!(
+            YoutubeUrl::parse("https://www.youtube.com/embed/dQw4w9WgXcQ?start=90"),
+            Some(expected(90))
+        );
+        assert_eq!(
+            YoutubeUrl::parse("https://www.youtube.com/watch?v=dQw4w9WgXcQ#t=1m30s"),
+            Some(expected(90))
+        );
+    }
+
+    #[test]
+    fn playlists() {
+        assert_eq!(
+            YoutubeUrl::parse(
+                "https://www.youtube.com/playlist?list=PLFgquLnL59alCl_2TQvOiD5Vgm1hCaGSI"
+            ),
+            Some(YoutubeUrl {
+                playlist: Some("PLFgquLnL59alCl_2TQvOiD5Vgm1hCaGSI".to_string()),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            YoutubeUrl::parse("https://www.youtube.com/watch?v=dQw4w9WgXcQ&list=PL123&index=2"),
+            Some(YoutubeUrl {
+                playlist: Some("PL123".to_string()),
+                ..video("dQw4w9WgXcQ")
+            })
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(YoutubeUrl::parse("never gonna give you up"), None);
+        assert_eq!(
+            YoutubeUrl::parse("https://example.com/watch?v=dQw4w9WgXcQ"),
+            None
+        );
+        assert_eq!(
+            YoutubeUrl::parse("https://www.youtube.com/watch?v=short"),
+            None
+        );
+        assert_eq!(YoutubeUrl::parse("https://www.youtube.com/"), None);
+        assert_eq!(
+            YoutubeUrl::parse("ftp://youtube.com/watch?v=dQw4w9WgXcQ"),
+            None
+        );
+    }
+}
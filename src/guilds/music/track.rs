@@ -1,50 +1,67 @@
-use invidious::CommonVideo;
+use std::{sync::Arc, time::Duration};
 
-#[derive(PartialEq)]
+use serenity::{all::UserId, model::Timestamp};
+
+use crate::helper::helper::format_duration;
+
+use super::source::Source;
+
+#[derive(Clone)]
 pub struct Track {
     pub title: String,
-    pub source: Source,
+    pub source: Arc<dyn Source>,
     pub thumbnail: Option<String>,
+    /// The artist or channel that made the track
+    pub author: Option<String>,
+    pub duration: Option<Duration>,
+    pub url: Option<String>,
+    /// The user that added the track, `None` if the bot added it by itself
+    pub requester: Option<UserId>,
+    pub enqueued_at: Timestamp,
 }
 
-impl Clone for Track {
-    fn clone(&self) -> Self {
+impl Track {
+    pub fn new(source: impl Source + 'static) -> Self {
+        let metadata = source.metadata();
+
         Track {
-            title: self.title.clone(),
-            source: self.source.clone(),
-            thumbnail: self.thumbnail.clone(),
+            title: metadata.title,
+            thumbnail: metadata.thumbnail,
+            author: metadata.author,
+            duration: metadata.duration,
+            url: source.url(),
+            requester: None,
+            enqueued_at: Timestamp::now(),
+            source: Arc::new(source),
         }
     }
-}
 
-impl Track {
-    pub fn from_youtube(video: CommonVideo) -> Self {
-        Track {
-            thumbnail: video.thumbnails.first().map(|t| t.url.clone()),
-            title: video.title.clone(),
-            source: Source::Youtube(video),
-        }
+    /// Marks the track as requested by the user just now
+    pub fn requested_by(mut self, user: UserId) -> Self {
+        self.requester = Some(user);
+        self.enqueued_at = Timestamp::now();
+        self
     }
-}
 
-/// T is the original resource for the source
-#[derive(Clone)]
-pub enum Source {
-    Youtube(CommonVideo),
-}
+    /// The title in bold, linking to the source when it has a link
+    pub fn linked_title(&self) -> String {
+        match &self.url {
+            Some(url) => format!("[**{}**]({})", self.title, url),
+            None => format!("**{}**", self.title),
+        }
+    }
 
-impl Source {
-    pub fn get_url(&self) -> String {
-        match self {
-            Source::Youtube(source) => format!("https://www.youtube.com/watch?v={}", source.id),
+    /// The duration formatted for display, or `--:--` if it's unknown
+    pub fn display_duration(&self) -> String {
+        match self.duration {
+            Some(duration) => format_duration(duration),
+            None => "--:--".to_string(),
         }
     }
 }
 
-impl PartialEq<Source> for Source {
-    fn eq(&self, other: &Source) -> bool {
-        match (self, other) {
-            (Source::Youtube(a), Source::Youtube(b)) => a.id == b.id,
-        }
+impl PartialEq for Track {
+    fn eq(&self, other: &Track) -> bool {
+        self.source.id() == other.source.id()
     }
 }
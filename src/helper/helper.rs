@@ -31,3 +31,30 @@ impl SendEmbed for ChannelId {
 pub fn to_ms(duration: Duration) -> String {
     return format!("{}ms", duration.as_millis());
 }
+
+/// Formats a duration as `m:ss`, or `h:mm:ss` when it's longer than an hour
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let (hours, minutes, seconds) = (seconds / 3600, (seconds / 60) % 60, seconds % 60);
+
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{}:{:02}", minutes, seconds)
+    }
+}
+
+/// Parses a timestamp such as `90`, `1:30` or `1:02:03` into a duration
+pub fn parse_timestamp(timestamp: &str) -> Option<Duration> {
+    let parts = timestamp
+        .split(':')
+        .map(|part| part.parse::<u64>().ok())
+        .collect::<Option<Vec<u64>>>()?;
+
+    if parts.is_empty() || parts.len() > 3 {
+        return None;
+    }
+
+    let seconds = parts.iter().fold(0, |total, part| total * 60 + part);
+    Some(Duration::from_secs(seconds))
+}
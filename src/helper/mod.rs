@@ -2,3 +2,6 @@ pub mod embed;
 pub mod emoji;
 pub mod helper;
 pub(crate) mod music;
+pub mod paginator;
+pub mod picker;
+pub mod settings;
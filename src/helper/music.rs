@@ -2,59 +2,314 @@
 
 use std::sync::Arc;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 
-use invidious::{hidden::SearchItem, ClientAsyncTrait, CommonVideo};
 use reqwest::Client;
 use serenity::{
-    all::{ChannelId, GuildId, Message},
+    all::{ChannelId, GuildId, Permissions, RoleId, UserId},
     async_trait,
     client::Context,
+    prelude::Mentionable,
 };
 use songbird::{
-    input::YoutubeDl, typemap::TypeMap, Event, EventContext, EventHandler, Songbird, TrackEvent,
+    input::File,
+    tracks::{PlayMode, TrackHandle},
+    Event, EventContext, EventHandler, Songbird, SongbirdKey, TrackEvent,
 };
-use tokio::sync::{Mutex, RwLockWriteGuard};
+use tokio::sync::Mutex;
 
 use crate::{
-    guilds::music::{handler::TrackEndNotifier, manager::MusicManager, track::Track},
-    YoutubeKey,
+    discord::{invocation::Invocation, roles},
+    guilds::{
+        data::GuildContext,
+        music::{
+            cache::AudioCache,
+            filter::Filters,
+            handler::{MusicHandler, TrackEndNotifier},
+            loudness::Normalization,
+            manager::MusicManager,
+            player,
+            track::Track,
+        },
+    },
+    helper::{embed, helper::format_duration, settings},
+    models::settings::GuildSettings,
+    HttpKey,
 };
 
-/// Connects if the bot is not connected to a voice channel, otherwise nothing happens
+/// Get the music manager for the specified guild
+pub async fn get_manager(ctx: &Context, guild: GuildId) -> Arc<Mutex<MusicManager>> {
+    let mut typemap = ctx.data.write().await;
+    let manager = typemap
+        .get_mut::<GuildContext>()
+        .expect("Expected GuildManager in TypeMap.");
+
+    Arc::clone(&manager.get(&guild).music)
+}
+
+/// Connects to the user's voice channel if the bot is not connected to a voice channel,
+/// otherwise nothing happens
 pub async fn ensure_connected(
     ctx: &Context,
     songbird: Arc<Songbird>,
     handler: Arc<Mutex<MusicManager>>,
-    msg: &Message,
+    guild: GuildId,
+    user: UserId,
 ) -> Result<()> {
-    if !is_connected(Arc::clone(&songbird), msg.guild_id.unwrap()).await {
-        let channel_id = get_voice_channel(ctx, msg).expect("Could not retrieve voice channel");
-        connect_to(songbird, handler, msg.guild_id.unwrap(), channel_id).await?;
+    if !is_connected(Arc::clone(&songbird), guild).await {
+        let channel_id = get_voice_channel(ctx, guild, user)
+            .ok_or_else(|| anyhow!("You need to be in a voice channel!"))?;
+        connect_to(songbird, handler, guild, channel_id).await?;
     }
 
     Ok(())
 }
 
 /// Get the channel id of the user's currently connected channel
-fn get_voice_channel(ctx: &Context, msg: &Message) -> Option<ChannelId> {
-    let guild = msg.guild(&ctx.cache).expect("Could not retrieve guild");
+pub fn get_voice_channel(ctx: &Context, guild: GuildId, user: UserId) -> Option<ChannelId> {
+    let guild = ctx.cache.guild(guild)?;
 
     let channel_id = guild
         .voice_states
-        .get(&msg.author.id)
+        .get(&user)
         .and_then(|voice_state| voice_state.channel_id);
 
     channel_id
 }
 
+/// Get the voice channel the bot is connected to
+pub fn get_bot_channel(ctx: &Context, guild: GuildId) -> Option<ChannelId> {
+    let guild = ctx.cache.guild(guild)?;
+    let bot = ctx.cache.current_user().id;
+
+    guild
+        .voice_states
+        .get(&bot)
+        .and_then(|voice_state| voice_state.channel_id)
+}
+
+/// Get the users, except for bots, that are in the same voice channel as the bot
+pub fn get_listeners(ctx: &Context, guild: GuildId) -> Vec<UserId> {
+    let Some(channel) = get_bot_channel(ctx, guild) else {
+        return Vec::new();
+    };
+
+    let Some(guild) = ctx.cache.guild(guild) else {
+        return Vec::new();
+    };
+
+    let bot = ctx.cache.current_user().id;
+    guild
+        .voice_states
+        .values()
+        .filter(|voice_state| voice_state.channel_id == Some(channel))
+        .filter(|voice_state| {
+            !voice_state
+                .member
+                .as_ref()
+                .is_some_and(|member| member.user.bot)
+        })
+        .map(|voice_state| voice_state.user_id)
+        .filter(|user| *user != bot)
+        .collect()
+}
+
+/// Check if the author may use destructive music commands, which is the case when they have the
+/// DJ role, can manage the guild, are alone with the bot, or when the guild has no DJ role
+pub async fn is_dj(invocation: &Invocation<'_>) -> Result<bool> {
+    let settings = settings::get_settings(invocation.ctx, invocation.guild_id()).await?;
+    if settings.dj_role().is_none() {
+        return Ok(true);
+    }
+
+    is_privileged(invocation).await
+}
+
+/// Check if the author has the DJ role, can manage the guild or is alone with the bot. Unlike
+/// [`is_dj`] this is `false` for everyone else when the guild has no DJ role, such as for skipping
+/// without a vote.
+pub async fn is_privileged(invocation: &Invocation<'_>) -> Result<bool> {
+    let ctx = invocation.ctx;
+    let guild = invocation.guild_id();
+    let role = settings::get_settings(ctx, guild).await?.dj_role();
+    let alone = get_listeners(ctx, guild) == vec![invocation.author().id];
+
+    let member = invocation.member().await?;
+    let manager = roles::has_permission(ctx, &member, Permissions::MANAGE_GUILD).await;
+
+    Ok(privileged(role, &member.roles, manager, alone))
+}
+
+fn privileged(dj_role: Option<RoleId>, roles: &[RoleId], manager: bool, alone: bool) -> bool {
+    alone || manager || dj_role.is_some_and(|role| roles.contains(&role))
+}
+
+/// Same as [`is_dj`], but tells the author why they aren't allowed when they aren't a DJ
+pub async fn require_dj(invocation: &Invocation<'_>) -> Result<bool> {
+    if is_dj(invocation).await? {
+        return Ok(true);
+    }
+
+    let role = settings::get_settings(invocation.ctx, invocation.guild_id())
+        .await?
+        .dj_role()
+        .map(|role| role.mention().to_string())
+        .unwrap_or_else(|| "DJ".to_string());
+
+    invocation
+        .error(embed::error(format!(
+            "You need the {} role to use this command, unless you're alone with the bot",
+            role
+        )))
+        .await?;
+
+    Ok(false)
+}
+
+/// Joins the user's voice channel unless the bot is already connected, and adds the tracks to the queue
+/// as far as the guild's limits allow. Returns how many tracks were added, or `None` after telling the
+/// user when they aren't in a voice channel or none of the tracks fit within the limits.
+pub async fn enqueue(invocation: &Invocation<'_>, tracks: &[Track]) -> Result<Option<usize>> {
+    let ctx = invocation.ctx;
+    let guild = invocation.guild_id();
+    let songbird = {
+        let typemap = ctx.data.read().await;
+        typemap
+            .get::<SongbirdKey>()
+            .expect("Expected SongbirdKey in TypeMap.")
+            .clone()
+    };
+
+    let manager = get_manager(ctx, guild).await;
+    let settings = settings::get_settings(ctx, guild).await?;
+    let queued = manager.lock().await.len();
+    let tracks = match within_limits(&settings, tracks, queued) {
+        Ok(tracks) => tracks,
+        Err(e) => {
+            invocation.error(embed::error(e)).await?;
+            return Ok(None);
+        }
+    };
+
+    let user = invocation.author().id;
+    if let Err(e) = ensure_connected(ctx, songbird, Arc::clone(&manager), guild, user).await {
+        invocation.error(embed::error(e.to_string())).await?;
+        return Ok(None);
+    }
+
+    let mut music = manager.lock().await;
+    attach_handler(ctx, &mut music, guild, invocation.channel_id()).await?;
+
+    match tracks.as_slice() {
+        [track] => music.add(track).await,
+        tracks => music.add_all(tracks).await,
+    }
+
+    Ok(Some(tracks.len()))
+}
+
+/// The tracks that don't last longer than the guild allows, as many as still fit in the queue
+fn within_limits(
+    settings: &GuildSettings,
+    tracks: &[Track],
+    queued: usize,
+) -> Result<Vec<Track>, String> {
+    let mut allowed = tracks
+        .iter()
+        .filter(|track| {
+            settings
+                .max_track_duration()
+                .zip(track.duration)
+                .is_none_or(|(max, duration)| duration <= max)
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+
+    if allowed.is_empty() {
+        let max = settings.max_track_duration().unwrap_or_default();
+        return Err(match tracks {
+            [_] => format!(
+                "This track is longer than {}, the longest this server allows!",
+                format_duration(max)
+            ),
+            _ => format!(
+                "Every track is longer than {}, the longest this server allows!",
+                format_duration(max)
+            ),
+        });
+    }
+
+    if let Some(max) = settings.max_queue_length() {
+        if queued >= max {
+            return Err(format!(
+                "The queue is full, this server allows at most {} tracks!",
+                max
+            ));
+        }
+
+        allowed.truncate(max - queued);
+    }
+
+    Ok(allowed)
+}
+
+/// Lets the music manager play through songbird and announce in the guild's music channel, or else
+/// the given channel, loading the playback settings of the guild. Nothing changes if it already has
+/// a handler, leaving drops the handler so the settings are loaded again on the next join.
+pub async fn attach_handler(
+    ctx: &Context,
+    music: &mut MusicManager,
+    guild: GuildId,
+    channel: ChannelId,
+) -> Result<()> {
+    if music.has_handler() {
+        return Ok(());
+    }
+
+    let (songbird, client) = {
+        let typemap = ctx.data.read().await;
+
+        let songbird = typemap
+            .get::<SongbirdKey>()
+            .expect("Expected SongbirdKey in TypeMap.")
+            .clone();
+
+        let client = typemap
+            .get::<HttpKey>()
+            .expect("Expected HttpKey in TypeMap.")
+            .clone();
+
+        (songbird, client)
+    };
+
+    let settings = settings::get_settings(ctx, guild).await?;
+    music.event_handler(Box::new(MusicHandler::new(
+        ctx.clone(),
+        songbird,
+        Arc::new(client),
+        guild,
+        settings.music_channel().unwrap_or(channel),
+    )));
+
+    apply_settings(music, &settings)
+}
+
+/// Sets up the playback of the music manager the way the guild configured it
+fn apply_settings(music: &mut MusicManager, settings: &GuildSettings) -> Result<()> {
+    music.set_volume(settings.default_volume())?;
+    music.set_crossfade(settings.crossfade());
+    music.set_fair(settings.fair_queue);
+
+    Ok(())
+}
+
 /// Check if the bot is connected to a voice channel
 async fn is_connected(songbird: Arc<Songbird>, guild: GuildId) -> bool {
     songbird.get(guild).is_some()
 }
 
 /// Connect the bot to the specified voice channel
-async fn connect_to(
+pub async fn connect_to(
     songbird: Arc<Songbird>,
     music_handler: Arc<Mutex<MusicManager>>,
     guild: GuildId,
@@ -63,8 +318,16 @@ async fn connect_to(
     if let Ok(handler_lock) = songbird.join(guild, channel).await {
         let mut handler = handler_lock.lock().await;
 
-        handler.add_global_event(TrackEvent::End.into(), TrackEndNotifier::new(music_handler));
-        handler.add_global_event(TrackEvent::Error.into(), TrackErrorNotifier);
+        handler.add_global_event(
+            TrackEvent::End.into(),
+            TrackEndNotifier::new(Arc::clone(&music_handler)),
+        );
+        handler.add_global_event(
+            TrackEvent::Error.into(),
+            TrackErrorNotifier {
+                handler: music_handler,
+            },
+        );
 
         if !handler.is_deaf() {
             let _ = handler.deafen(true).await;
@@ -74,6 +337,21 @@ async fn connect_to(
     Ok(())
 }
 
+/// Leaves the voice channel and forgets the queue
+pub async fn leave(ctx: &Context, guild: GuildId) -> Result<()> {
+    let songbird = songbird::get(ctx)
+        .await
+        .ok_or_else(|| anyhow!("Songbird was not registered"))?;
+
+    songbird.remove(guild).await?;
+
+    let manager = get_manager(ctx, guild).await;
+    manager.lock().await.reset();
+    player::refresh(ctx, guild);
+
+    Ok(())
+}
+
 /// Stops the current track completely
 pub async fn stop_playing(songbird: Arc<Songbird>, guild: GuildId) -> Result<()> {
     if let Some(handler_lock) = songbird.get(guild) {
@@ -84,79 +362,124 @@ pub async fn stop_playing(songbird: Arc<Songbird>, guild: GuildId) -> Result<()>
     Ok(())
 }
 
-/// Query youtube for videos that match the query
-pub async fn query_youtube(
-    data: &RwLockWriteGuard<'_, TypeMap>,
-    query: &str,
-) -> Result<Vec<CommonVideo>> {
-    let do_search = !query.starts_with("http");
-    let client = data
-        .get::<YoutubeKey>()
-        .expect("Expected YoutubeKey in TypeMap.")
-        .clone();
-
-    let videos = if do_search {
-        let search = client
-            .search(Some(&format!("q={}", query)))
-            .await
-            .expect("Failed to search for tracks");
-
-        search.items.iter().filter_map(is_video).collect::<Vec<_>>()
-    } else {
-        let id = query.split("?v=").last().unwrap();
-        let video: CommonVideo = client
-            .video(id, None)
-            .await
-            .expect("Failed to find video")
-            .into();
-        vec![video]
-    };
-
-    Ok(videos)
-}
-
+/// Starts playing the track, returning the handle of the songbird track
 pub async fn play_track(
     songbird: Arc<Songbird>,
     client: Arc<Client>,
     guild: GuildId,
     track: &Track,
-) -> Result<()> {
+    cache: Option<&AudioCache>,
+    filters: &Filters,
+    normalization: Option<Normalization>,
+) -> Result<TrackHandle> {
     let client = Arc::as_ref(&client);
+    let handler_lock = songbird
+        .get(guild)
+        .ok_or_else(|| anyhow!("Not connected to a voice channel"))?;
 
-    if let Some(handler_lock) = songbird.get(guild) {
-        let mut handler = handler_lock.lock().await;
+    let cached = cache.and_then(|cache| cache.get(&track.source.id()));
+    let input = match cached {
+        Some(path) => File::new(path).into(),
+        None => track.source.input(client).await?,
+    };
+    let input = filters.apply(input, normalization).await?;
 
-        let source = track.source.get_url();
+    let mut handler = handler_lock.lock().await;
+    let handle = handler.play_input(input);
 
-        let youtubedl = YoutubeDl::new(client.clone(), source);
-        let _ = handler.play_input(youtubedl.into());
+    if let Some(start) = track.source.start() {
+        let _ = handle.seek(start.div_f64(filters.time_scale()));
     }
 
-    Ok(())
+    Ok(handle)
 }
 
-fn is_video(item: &SearchItem) -> Option<CommonVideo> {
-    return match item {
-        SearchItem::Video(v) => Some(v.clone()),
-        _ => None,
-    };
+/// Retries or skips the current track when songbird fails to play it
+struct TrackErrorNotifier {
+    handler: Arc<Mutex<MusicManager>>,
 }
 
-struct TrackErrorNotifier;
-
 #[async_trait]
 impl EventHandler for TrackErrorNotifier {
     async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
         if let EventContext::Track(track_list) = ctx {
+            let mut manager = self.handler.lock().await;
+
             for (state, handle) in *track_list {
-                println!(
-                    "Track {:?} encountered an error: {:?}",
-                    handle.uuid(),
-                    state.playing
-                );
+                if !manager.is_current(handle) {
+                    continue;
+                }
+
+                let reason = match &state.playing {
+                    PlayMode::Errored(why) => why.to_string(),
+                    playing => format!("{:?}", playing),
+                };
+
+                manager.fail_current(reason, state.position).await;
             }
         }
 
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::guilds::music::{event::MusicEventHandler, filter::Filters};
+
+    use super::*;
+
+    struct SilentHandler;
+
+    #[async_trait]
+    impl MusicEventHandler for SilentHandler {
+        async fn on_track_start(&mut self, _: &Track, _: &Filters) -> Result<TrackHandle> {
+            Err(anyhow!("Nothing plays in tests"))
+        }
+    }
+
+    #[test]
+    fn changed_settings_are_used_after_rejoining() {
+        let mut music = MusicManager::default();
+        let mut settings = GuildSettings::new(GuildId::new(1));
+        music.event_handler(Box::new(SilentHandler));
+        apply_settings(&mut music, &settings).unwrap();
+
+        settings.default_volume = 50;
+        settings.crossfade = 4;
+        music.reset();
+
+        // Without a handler the next join attaches a new one and applies the settings again
+        assert!(!music.has_handler());
+        apply_settings(&mut music, &settings).unwrap();
+        assert_eq!(music.volume(), 0.5);
+        assert_eq!(music.crossfade(), Duration::from_secs(4));
+    }
+
+    #[test]
+    fn nobody_is_privileged_without_a_dj_role() {
+        let roles = [RoleId::new(1)];
+
+        assert!(!privileged(None, &roles, false, false));
+        assert!(privileged(None, &roles, true, false));
+        assert!(privileged(None, &roles, false, true));
+    }
+
+    #[test]
+    fn the_dj_role_is_privileged() {
+        assert!(privileged(
+            Some(RoleId::new(1)),
+            &[RoleId::new(1)],
+            false,
+            false
+        ));
+        assert!(!privileged(
+            Some(RoleId::new(2)),
+            &[RoleId::new(1)],
+            false,
+            false
+        ));
+    }
+}
@@ -0,0 +1,239 @@
+//! Shows long lists over multiple pages that can be flipped through with buttons
+
+use std::time::Duration;
+
+use anyhow::Result;
+use serenity::{
+    all::{ButtonStyle, ComponentInteraction, UserId},
+    builder::{
+        CreateActionRow, CreateButton, CreateEmbed, CreateEmbedFooter, CreateInteractionResponse,
+        CreateInteractionResponseMessage, CreateQuickModal, EditMessage,
+    },
+    client::Context,
+    prelude::Mentionable,
+};
+
+use crate::discord::invocation::Invocation;
+
+use super::embed;
+
+/// Discord doesn't allow longer embed descriptions
+const MAX_DESCRIPTION_LENGTH: usize = 4096;
+/// How long the buttons keep working after they were last pressed
+const TIMEOUT: Duration = Duration::from_secs(120);
+/// How long the user has to fill in the page to jump to
+const JUMP_TIMEOUT: Duration = Duration::from_secs(60);
+
+const PREVIOUS: &str = "paginator:previous";
+const NEXT: &str = "paginator:next";
+const JUMP: &str = "paginator:jump";
+
+pub struct Paginator {
+    /// Shown above every page
+    header: String,
+    pages: Vec<String>,
+}
+
+impl Paginator {
+    /// Splits the lines into pages of at most `per_page` lines, a page ends early when it would no
+    /// longer fit in an embed along with the header
+    pub fn new(
+        header: impl Into<String>,
+        lines: impl IntoIterator<Item = String>,
+        per_page: usize,
+    ) -> Self {
+        let header = header.into();
+        let max_length = MAX_DESCRIPTION_LENGTH.saturating_sub(header.chars().count());
+
+        let mut pages = Vec::new();
+        let mut page: Vec<String> = Vec::new();
+        let mut length = 0;
+        for line in lines {
+            let line_length = line.chars().count() + 1;
+            if !page.is_empty() && (page.len() == per_page || length + line_length > max_length) {
+                pages.push(page.join("\n"));
+                page.clear();
+                length = 0;
+            }
+
+            length += line_length;
+            page.push(line);
+        }
+
+        if !page.is_empty() || pages.is_empty() {
+            pages.push(page.join("\n"));
+        }
+
+        Paginator { header, pages }
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Shows the page, counting from 0, as the reply to the command. Only the user of the command may
+    /// flip through the pages, the buttons are disabled once they weren't pressed for the timeout.
+    pub async fn show(&self, invocation: &Invocation<'_>, page: usize) -> Result<()> {
+        let mut page = page.min(self.page_count() - 1);
+        if self.page_count() == 1 {
+            invocation.reply(self.page(page)).await?;
+            return Ok(());
+        }
+
+        let ctx = invocation.ctx;
+        let author = invocation.author().id;
+        let mut message = invocation
+            .send(self.page(page), self.buttons(page, false))
+            .await?;
+
+        while let Some(interaction) = message
+            .await_component_interaction(&ctx.shard)
+            .timeout(TIMEOUT)
+            .await
+        {
+            if interaction.user.id != author {
+                deny(ctx, &interaction, author).await?;
+                continue;
+            }
+
+            page = match interaction.data.custom_id.as_str() {
+                PREVIOUS => page.saturating_sub(1),
+                NEXT => (page + 1).min(self.page_count() - 1),
+                JUMP => {
+                    self.jump(ctx, &interaction, page).await?;
+                    continue;
+                }
+                _ => continue,
+            };
+
+            interaction
+                .create_response(&ctx.http, self.update(page))
+                .await?;
+        }
+
+        message
+            .edit(ctx, EditMessage::new().components(self.buttons(page, true)))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Asks which page to show and shows it, the page stays the same when the answer isn't a page
+    async fn jump(
+        &self,
+        ctx: &Context,
+        interaction: &ComponentInteraction,
+        current: usize,
+    ) -> Result<()> {
+        let modal = CreateQuickModal::new("Jump to page")
+            .timeout(JUMP_TIMEOUT)
+            .short_field(format!("Page (1-{})", self.page_count()));
+
+        let Some(response) = interaction.quick_modal(ctx, modal).await? else {
+            return Ok(());
+        };
+
+        let page = response
+            .inputs
+            .first()
+            .and_then(|input| input.trim().parse::<usize>().ok())
+            .filter(|page| (1..=self.page_count()).contains(page))
+            .map_or(current, |page| page - 1);
+
+        response
+            .interaction
+            .create_response(&ctx.http, self.update(page))
+            .await?;
+
+        Ok(())
+    }
+
+    fn page(&self, page: usize) -> CreateEmbed {
+        let embed = embed::build(format!("{}{}", self.header, self.pages[page]));
+        if self.page_count() == 1 {
+            return embed;
+        }
+
+        embed.footer(CreateEmbedFooter::new(format!(
+            "Page {}/{}",
+            page + 1,
+            self.page_count()
+        )))
+    }
+
+    fn update(&self, page: usize) -> CreateInteractionResponse {
+        CreateInteractionResponse::UpdateMessage(
+            CreateInteractionResponseMessage::new()
+                .embed(self.page(page))
+                .components(self.buttons(page, false)),
+        )
+    }
+
+    fn buttons(&self, page: usize, disabled: bool) -> Vec<CreateActionRow> {
+        vec![CreateActionRow::Buttons(vec![
+            CreateButton::new(PREVIOUS)
+                .emoji('◀')
+                .style(ButtonStyle::Secondary)
+                .disabled(disabled || page == 0),
+            CreateButton::new(JUMP)
+                .label(format!("{}/{}", page + 1, self.page_count()))
+                .style(ButtonStyle::Secondary)
+                .disabled(disabled),
+            CreateButton::new(NEXT)
+                .emoji('▶')
+                .style(ButtonStyle::Secondary)
+                .disabled(disabled || page + 1 == self.page_count()),
+        ])]
+    }
+}
+
+/// Tells a user that isn't allowed to flip through the pages who is
+async fn deny(ctx: &Context, interaction: &ComponentInteraction, author: UserId) -> Result<()> {
+    let message = CreateInteractionResponseMessage::new()
+        .embed(embed::error(format!(
+            "Only {} can flip through these pages, use the command yourself to browse them",
+            author.mention()
+        )))
+        .ephemeral(true);
+
+    interaction
+        .create_response(&ctx.http, CreateInteractionResponse::Message(message))
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(count: usize) -> Vec<String> {
+        (1..=count).map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn splits_lines_into_pages() {
+        let paginator = Paginator::new("", lines(25), 10);
+
+        assert_eq!(paginator.page_count(), 3);
+        assert_eq!(paginator.pages[0], lines(10).join("\n"));
+        assert_eq!(paginator.pages[2], "21\n22\n23\n24\n25");
+    }
+
+    #[test]
+    fn long_lines_end_a_page_early() {
+        let line = "a".repeat(1500);
+        let paginator = Paginator::new("header\n", vec![line; 5], 10);
+
+        assert_eq!(paginator.page_count(), 3);
+        assert!(paginator
+            .pages
+            .iter()
+            .all(|page| page.chars().count() + 7 <= MAX_DESCRIPTION_LENGTH));
+    }
+
+    #[test]
+    fn nothing_to_show_is_a_single_page() {
+        assert_eq!(Paginator::new("Empty", Vec::new(), 10).page_count(), 1);
+    }
+}
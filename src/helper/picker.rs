@@ -0,0 +1,131 @@
+//! Lets a user pick one option out of a list through a select menu
+
+use std::time::Duration;
+
+use anyhow::Result;
+use serenity::{
+    all::ComponentInteractionDataKind,
+    builder::{
+        CreateActionRow, CreateInteractionResponse, CreateInteractionResponseMessage,
+        CreateSelectMenu, CreateSelectMenuKind, CreateSelectMenuOption, EditMessage,
+    },
+};
+
+use crate::discord::invocation::Invocation;
+
+use super::embed;
+
+/// Discord doesn't allow more options in a single select menu
+const MAX_OPTIONS: usize = 25;
+/// Discord doesn't allow longer labels or descriptions for select menu options
+const MAX_LABEL_LENGTH: usize = 100;
+
+pub struct PickOption {
+    pub label: String,
+    pub description: String,
+}
+
+impl PickOption {
+    pub fn new(label: impl Into<String>, description: impl Into<String>) -> Self {
+        PickOption {
+            label: label.into(),
+            description: description.into(),
+        }
+    }
+}
+
+/// Shows the options to the user of the command and waits for them to pick one,
+/// returns the index of the picked option or `None` if they didn't pick one in time
+pub async fn pick(
+    invocation: &Invocation<'_>,
+    title: &str,
+    options: Vec<PickOption>,
+    timeout: Duration,
+) -> Result<Option<usize>> {
+    let options = options.into_iter().take(MAX_OPTIONS).collect::<Vec<_>>();
+
+    let body = options
+        .iter()
+        .enumerate()
+        .map(|(i, option)| format!("`{}.` **{}** - {}", i + 1, option.label, option.description))
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    let menu = CreateSelectMenu::new(
+        "picker",
+        CreateSelectMenuKind::String {
+            options: options
+                .iter()
+                .enumerate()
+                .map(|(i, option)| {
+                    CreateSelectMenuOption::new(truncate(&option.label), i.to_string())
+                        .description(truncate(&option.description))
+                })
+                .collect(),
+        },
+    )
+    .placeholder(title);
+
+    let ctx = invocation.ctx;
+    let mut message = invocation
+        .send(
+            embed::build(format!("**{}**\n{}", title, body)),
+            vec![CreateActionRow::SelectMenu(menu)],
+        )
+        .await?;
+
+    let interaction = message
+        .await_component_interaction(&ctx.shard)
+        .author_id(invocation.author().id)
+        .timeout(timeout)
+        .await;
+
+    let Some(interaction) = interaction else {
+        message
+            .edit(
+                &ctx,
+                EditMessage::new()
+                    .embed(embed::error("You didn't pick anything in time!"))
+                    .components(vec![]),
+            )
+            .await?;
+
+        return Ok(None);
+    };
+
+    let index = match &interaction.data.kind {
+        ComponentInteractionDataKind::StringSelect { values } => values
+            .first()
+            .and_then(|value| value.parse::<usize>().ok())
+            .filter(|index| *index < options.len()),
+        _ => None,
+    };
+
+    let response = match index {
+        Some(index) => embed::build(format!("Picked **{}**", options[index].label)),
+        None => embed::error("That isn't one of the options!"),
+    };
+
+    interaction
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::UpdateMessage(
+                CreateInteractionResponseMessage::new()
+                    .embed(response)
+                    .components(vec![]),
+            ),
+        )
+        .await?;
+
+    Ok(index)
+}
+
+fn truncate(text: &str) -> String {
+    if text.chars().count() <= MAX_LABEL_LENGTH {
+        return text.to_string();
+    }
+
+    let mut truncated = text.chars().take(MAX_LABEL_LENGTH - 3).collect::<String>();
+    truncated.push_str("...");
+    truncated
+}
@@ -0,0 +1,63 @@
+//! Loads and saves guild settings from the database, keeping them in the guild data so they don't
+//! have to be loaded again for every message
+
+use anyhow::Result;
+use serenity::{all::GuildId, client::Context};
+
+use crate::{guilds::data::GuildContext, models::settings::GuildSettings, DatabaseKey};
+
+pub async fn get_settings(ctx: &Context, guild: GuildId) -> Result<GuildSettings> {
+    let database = {
+        let typemap = ctx.data.read().await;
+        let cached = typemap
+            .get::<GuildContext>()
+            .expect("Expected GuildManager in TypeMap.")
+            .find(&guild)
+            .and_then(|data| data.settings.clone());
+
+        if let Some(settings) = cached {
+            return Ok(settings);
+        }
+
+        typemap
+            .get::<DatabaseKey>()
+            .expect("Expected DatabaseKey in TypeMap.")
+            .clone()
+    };
+
+    let settings = {
+        let mut connection = database.lock().await;
+        GuildSettings::load(&mut connection, guild)?
+    };
+
+    // The settings may have been saved while they were loading, those are newer than these
+    let mut typemap = ctx.data.write().await;
+    let guilds = typemap
+        .get_mut::<GuildContext>()
+        .expect("Expected GuildManager in TypeMap.");
+
+    Ok(guilds.get(&guild).settings.get_or_insert(settings).clone())
+}
+
+pub async fn save_settings(ctx: &Context, settings: &GuildSettings) -> Result<()> {
+    let database = {
+        let typemap = ctx.data.read().await;
+        typemap
+            .get::<DatabaseKey>()
+            .expect("Expected DatabaseKey in TypeMap.")
+            .clone()
+    };
+
+    {
+        let mut connection = database.lock().await;
+        settings.save(&mut connection)?;
+    }
+
+    let mut typemap = ctx.data.write().await;
+    let guilds = typemap
+        .get_mut::<GuildContext>()
+        .expect("Expected GuildManager in TypeMap.");
+
+    guilds.get(&settings.guild()).settings = Some(settings.clone());
+    Ok(())
+}
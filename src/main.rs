@@ -4,8 +4,8 @@ use std::sync::Arc;
 use anyhow::Result;
 use config::Config;
 use diesel::{Connection, SqliteConnection};
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
 
-use invidious::ClientAsync;
 use reqwest::Client as HttpClient;
 use serenity::framework::standard::Configuration;
 use serenity::framework::StandardFramework;
@@ -14,14 +14,26 @@ use serenity::prelude::*;
 use serenity::{prelude::GatewayIntents, Client};
 use songbird::SerenityInit;
 
+use crate::command::autocomplete::AutocompleteKey;
+use crate::discord::events::Events;
 use crate::guilds::data::{GuildContext, GuildManager};
+use crate::guilds::music::source::{
+    http::HttpProvider, local, local::LocalProvider, youtube::YoutubeProvider, SourceRegistry,
+};
+use crate::guilds::music::{
+    cache::{AudioCache, CacheKey},
+    failures::FailureKey,
+    persist,
+};
 
 pub mod command;
 pub mod config;
 pub mod discord;
 pub mod guilds;
 pub mod helper;
-// pub mod models;
+pub mod models;
+
+const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -46,12 +58,15 @@ async fn main() -> Result<()> {
     };
 
     let framework = StandardFramework::new()
-        .group(&command::general::GENERAL_GROUP)
-        .group(&command::music::MUSIC_GROUP);
+        .group(&command::prefix::GENERAL_GROUP)
+        .group(&command::prefix::MUSIC_GROUP)
+        .group(&command::prefix::PLAYLISTS_GROUP);
 
     framework.configure(
+        // Every guild can choose its own prefix, an empty prefix turns off the default one
         Configuration::new()
-            .prefix("!")
+            .prefix("")
+            .dynamic_prefix(command::prefix::guild_prefix)
             .case_insensitivity(true)
             .on_mention(Some(bot_id))
             .owners(owners)
@@ -64,25 +79,76 @@ async fn main() -> Result<()> {
         | GatewayIntents::GUILD_VOICE_STATES
         | GatewayIntents::MESSAGE_CONTENT;
 
-    let connection = SqliteConnection::establish("data.db")
+    let mut connection = SqliteConnection::establish("data.db")
         .unwrap_or_else(|_| panic!("Failed to connect to database"));
+    connection
+        .run_pending_migrations(MIGRATIONS)
+        .unwrap_or_else(|why| panic!("Failed to run migrations: {:?}", why));
+
+    let database = Arc::new(Mutex::new(connection));
+
+    let mut sources = SourceRegistry::new(YoutubeProvider::new(
+        ClientAsync::default(),
+        config.music.playlist_limit,
+    ));
+    if let Some(library) = config.music.library.clone() {
+        sources = sources.register(LocalProvider::new(Arc::clone(&database)));
+
+        let database = Arc::clone(&database);
+        tokio::task::spawn_blocking(move || match local::scan(&database, &library) {
+            Ok(updated) => {
+                println!("Indexed {} new or changed files in {:?}", updated, library)
+            }
+            Err(why) => println!("Failed to index music library: {:?}", why),
+        });
+    }
+    let sources = sources.register(HttpProvider);
+
+    let cache = config.music.cache.clone().map(|directory| {
+        let max_size = config.music.cache_size * 1024 * 1024;
+        AudioCache::open(directory, max_size)
+            .map(Arc::new)
+            .unwrap_or_else(|why| panic!("Failed to open audio cache: {:?}", why))
+    });
 
     let mut client = Client::builder(&config.token, intents)
         .framework(framework)
+        .event_handler(Events::default())
         .register_songbird()
         .type_map_insert::<HttpKey>(HttpClient::new())
-        .type_map_insert::<DatabaseKey>(Arc::new(Mutex::new(connection)))
+        .type_map_insert::<DatabaseKey>(database)
         .type_map_insert::<ConfigKey>(config)
         .type_map_insert::<GuildContext>(GuildManager::new())
-        .type_map_insert::<YoutubeKey>(ClientAsync::default())
+        .type_map_insert::<SourceKey>(Arc::new(sources))
+        .type_map_insert::<FailureKey>(Default::default())
+        .type_map_insert::<AutocompleteKey>(Default::default())
         .await
         .expect("Error creating client");
 
+    if let Some(cache) = cache {
+        client.data.write().await.insert::<CacheKey>(cache);
+    }
+
     println!(
         "Started running as {}",
         client.http.get_current_user().await?.name
     );
 
+    persist::spawn_autosave(client.data.clone());
+
+    // Save every queue one last time before shutting down, so it can be picked up after restarting
+    let data = client.data.clone();
+    let shard_manager = client.shard_manager.clone();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+
+        if let Err(why) = persist::save_all(&data, true).await {
+            println!("Failed to save queues: {:?}", why);
+        }
+
+        shard_manager.shutdown_all().await;
+    });
+
     if let Err(why) = client.start().await {
         println!("Client error: {:?}", why);
     }
@@ -90,11 +156,32 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+/// Waits until the bot is asked to stop, either through ctrl+c or by a service manager such as
+/// systemd or docker, which send SIGTERM
+#[cfg(unix)]
+async fn shutdown_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate =
+        signal(SignalKind::terminate()).expect("Could not register SIGTERM handler");
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => result.expect("Could not register ctrl+c handler"),
+        _ = terminate.recv() => {}
+    }
+}
+
+#[cfg(not(unix))]
+async fn shutdown_signal() {
+    tokio::signal::ctrl_c()
+        .await
+        .expect("Could not register ctrl+c handler");
+}
+
 pub struct Bot;
 pub struct DatabaseKey;
 pub struct ConfigKey;
 pub struct HttpKey;
-pub struct YoutubeKey;
+pub struct SourceKey;
 
 impl TypeMapKey for HttpKey {
     type Value = HttpClient;
@@ -108,6 +195,6 @@ impl TypeMapKey for ConfigKey {
     type Value = Config;
 }
 
-impl TypeMapKey for YoutubeKey {
-    type Value = ClientAsync;
+impl TypeMapKey for SourceKey {
+    type Value = Arc<SourceRegistry>;
 }
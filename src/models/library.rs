@@ -0,0 +1,57 @@
+use diesel::prelude::*;
+
+use super::schema::library::{self, dsl};
+
+/// A single audio file in the local music library
+#[derive(Insertable, Queryable, Selectable, Clone)]
+#[diesel(table_name = library)]
+pub struct LibraryEntry {
+    pub path: String,
+    pub title: String,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    /// Length of the file in seconds
+    pub duration: Option<i32>,
+    /// When the file was last modified, in seconds since the unix epoch
+    pub modified: i64,
+}
+
+impl LibraryEntry {
+    /// Find entries where the title, artist or album contains the query
+    pub fn search(
+        connection: &mut SqliteConnection,
+        query: &str,
+        limit: i64,
+    ) -> QueryResult<Vec<LibraryEntry>> {
+        let pattern = format!("%{}%", query);
+
+        dsl::library
+            .filter(
+                dsl::title
+                    .like(&pattern)
+                    .or(dsl::artist.like(&pattern))
+                    .or(dsl::album.like(&pattern)),
+            )
+            .order(dsl::title.asc())
+            .limit(limit)
+            .select(LibraryEntry::as_select())
+            .load(connection)
+    }
+
+    /// Get the path and modification time of every indexed file
+    pub fn indexed(connection: &mut SqliteConnection) -> QueryResult<Vec<(String, i64)>> {
+        dsl::library
+            .select((dsl::path, dsl::modified))
+            .load(connection)
+    }
+
+    pub fn save(&self, connection: &mut SqliteConnection) -> QueryResult<usize> {
+        diesel::replace_into(dsl::library)
+            .values(self)
+            .execute(connection)
+    }
+
+    pub fn delete(connection: &mut SqliteConnection, path: &str) -> QueryResult<usize> {
+        diesel::delete(dsl::library.filter(dsl::path.eq(path))).execute(connection)
+    }
+}
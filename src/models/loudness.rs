@@ -0,0 +1,28 @@
+use diesel::prelude::*;
+
+use super::schema::loudness::{self, dsl};
+
+/// The measured loudness of a source, so it only has to be measured once
+#[derive(Insertable, Queryable, Selectable)]
+#[diesel(table_name = loudness)]
+pub struct Loudness {
+    pub source: String,
+    /// Integrated loudness of the whole track in LUFS
+    pub integrated: f64,
+}
+
+impl Loudness {
+    pub fn get(connection: &mut SqliteConnection, source: &str) -> QueryResult<Option<f64>> {
+        dsl::loudness
+            .find(source)
+            .select(dsl::integrated)
+            .first(connection)
+            .optional()
+    }
+
+    pub fn save(&self, connection: &mut SqliteConnection) -> QueryResult<usize> {
+        diesel::replace_into(dsl::loudness)
+            .values(self)
+            .execute(connection)
+    }
+}
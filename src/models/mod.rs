@@ -1,2 +1,7 @@
+pub mod library;
+pub mod loudness;
+pub mod playlist;
+pub mod queue;
+pub mod recent;
 pub mod schema;
-pub mod test;
+pub mod settings;
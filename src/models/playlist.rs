@@ -0,0 +1,155 @@
+use diesel::prelude::*;
+use serenity::all::{GuildId, UserId};
+
+use super::schema::{playlist_tracks, playlists};
+
+/// Who a playlist belongs to
+#[derive(Clone, Copy, PartialEq)]
+pub enum Owner {
+    User(UserId),
+    Guild(GuildId),
+}
+
+impl Owner {
+    fn id(&self) -> i64 {
+        match self {
+            Owner::User(user) => user.get() as i64,
+            Owner::Guild(guild) => guild.get() as i64,
+        }
+    }
+
+    fn is_guild(&self) -> bool {
+        matches!(self, Owner::Guild(_))
+    }
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = playlists)]
+pub struct Playlist {
+    pub id: i32,
+    pub name: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = playlists)]
+struct NewPlaylist<'a> {
+    /// Id of the user or guild the playlist belongs to
+    owner_id: i64,
+    /// Whether the playlist belongs to a guild instead of a user
+    guild: bool,
+    name: &'a str,
+}
+
+/// A track of a playlist, in the order they were added
+#[derive(Insertable, Queryable, Selectable, Clone)]
+#[diesel(table_name = playlist_tracks)]
+pub struct PlaylistTrack {
+    pub playlist_id: i32,
+    pub track_index: i32,
+    /// Id of the source the track can be restored from
+    pub source: String,
+    pub title: String,
+    pub author: Option<String>,
+    /// Length of the track in seconds
+    pub duration: Option<i32>,
+    pub url: Option<String>,
+    pub thumbnail: Option<String>,
+}
+
+impl Playlist {
+    pub fn find(
+        connection: &mut SqliteConnection,
+        owner: Owner,
+        name: &str,
+    ) -> QueryResult<Option<Playlist>> {
+        playlists::table
+            .filter(playlists::owner_id.eq(owner.id()))
+            .filter(playlists::guild.eq(owner.is_guild()))
+            .filter(playlists::name.eq(name))
+            .select(Playlist::as_select())
+            .first(connection)
+            .optional()
+    }
+
+    /// The user's playlist with the name, or the guild's when the user has none by that name
+    pub fn find_playable(
+        connection: &mut SqliteConnection,
+        user: UserId,
+        guild: Option<GuildId>,
+        name: &str,
+    ) -> QueryResult<Option<Playlist>> {
+        match (Playlist::find(connection, Owner::User(user), name)?, guild) {
+            (None, Some(guild)) => Playlist::find(connection, Owner::Guild(guild), name),
+            (playlist, _) => Ok(playlist),
+        }
+    }
+
+    /// Every playlist of the owner, sorted by name
+    pub fn list(connection: &mut SqliteConnection, owner: Owner) -> QueryResult<Vec<Playlist>> {
+        playlists::table
+            .filter(playlists::owner_id.eq(owner.id()))
+            .filter(playlists::guild.eq(owner.is_guild()))
+            .order(playlists::name.asc())
+            .select(Playlist::as_select())
+            .load(connection)
+    }
+
+    pub fn create(
+        connection: &mut SqliteConnection,
+        owner: Owner,
+        name: &str,
+    ) -> QueryResult<Playlist> {
+        diesel::insert_into(playlists::table)
+            .values(NewPlaylist {
+                owner_id: owner.id(),
+                guild: owner.is_guild(),
+                name,
+            })
+            .execute(connection)?;
+
+        Playlist::find(connection, owner, name)?.ok_or(diesel::result::Error::NotFound)
+    }
+
+    pub fn delete(&self, connection: &mut SqliteConnection) -> QueryResult<()> {
+        connection.transaction(|connection| {
+            diesel::delete(playlist_tracks::table.filter(playlist_tracks::playlist_id.eq(self.id)))
+                .execute(connection)?;
+            diesel::delete(playlists::table.find(self.id)).execute(connection)?;
+
+            Ok(())
+        })
+    }
+
+    /// The tracks of the playlist in order
+    pub fn tracks(&self, connection: &mut SqliteConnection) -> QueryResult<Vec<PlaylistTrack>> {
+        playlist_tracks::table
+            .filter(playlist_tracks::playlist_id.eq(self.id))
+            .order(playlist_tracks::track_index.asc())
+            .select(PlaylistTrack::as_select())
+            .load(connection)
+    }
+
+    /// Replaces the tracks of the playlist, their ids and indices are filled in
+    pub fn set_tracks(
+        &self,
+        connection: &mut SqliteConnection,
+        mut tracks: Vec<PlaylistTrack>,
+    ) -> QueryResult<()> {
+        for (index, track) in tracks.iter_mut().enumerate() {
+            track.playlist_id = self.id;
+            track.track_index = index as i32;
+        }
+
+        connection.transaction(|connection| {
+            diesel::delete(playlist_tracks::table.filter(playlist_tracks::playlist_id.eq(self.id)))
+                .execute(connection)?;
+            if !tracks.is_empty() {
+                diesel::insert_into(playlist_tracks::table)
+                    .values(&tracks)
+                    .execute(connection)?;
+            }
+
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,91 @@
+use diesel::prelude::*;
+
+use super::schema::{queued_tracks, saved_queues};
+
+/// The state of a guild's music when it was last saved
+#[derive(Insertable, Queryable, Selectable)]
+#[diesel(table_name = saved_queues)]
+pub struct SavedQueue {
+    pub guild_id: i64,
+    pub voice_channel: i64,
+    pub text_channel: i64,
+    pub loop_mode: String,
+    /// Volume in percent
+    pub volume: i32,
+    /// How far into the first track playback was, in milliseconds
+    pub position: i64,
+}
+
+/// A track of a saved queue, the first one was playing when it was saved
+#[derive(Insertable, Queryable, Selectable)]
+#[diesel(table_name = queued_tracks)]
+pub struct QueuedTrack {
+    pub guild_id: i64,
+    pub track_index: i32,
+    /// Id of the source the track can be restored from
+    pub source: String,
+    pub title: String,
+    pub author: Option<String>,
+    /// Length of the track in seconds
+    pub duration: Option<i32>,
+    pub url: Option<String>,
+    pub thumbnail: Option<String>,
+    pub requester: Option<i64>,
+    /// When the track was added, in seconds since the unix epoch
+    pub enqueued_at: i64,
+}
+
+impl SavedQueue {
+    /// Replaces the saved queue of the guild
+    pub fn save(
+        &self,
+        connection: &mut SqliteConnection,
+        tracks: &[QueuedTrack],
+    ) -> QueryResult<()> {
+        connection.transaction(|connection| {
+            SavedQueue::delete(connection, self.guild_id)?;
+
+            diesel::insert_into(saved_queues::table)
+                .values(self)
+                .execute(connection)?;
+            if !tracks.is_empty() {
+                diesel::insert_into(queued_tracks::table)
+                    .values(tracks)
+                    .execute(connection)?;
+            }
+
+            Ok(())
+        })
+    }
+
+    pub fn delete(connection: &mut SqliteConnection, guild: i64) -> QueryResult<()> {
+        diesel::delete(saved_queues::table.filter(saved_queues::guild_id.eq(guild)))
+            .execute(connection)?;
+        diesel::delete(queued_tracks::table.filter(queued_tracks::guild_id.eq(guild)))
+            .execute(connection)?;
+
+        Ok(())
+    }
+
+    /// Loads every saved queue along with its tracks in order
+    pub fn load_all(
+        connection: &mut SqliteConnection,
+    ) -> QueryResult<Vec<(SavedQueue, Vec<QueuedTrack>)>> {
+        let queues = saved_queues::table
+            .select(SavedQueue::as_select())
+            .load(connection)?;
+
+        queues
+            .into_iter()
+            .map(|queue| {
+                let tracks = queued_tracks::table
+                    .filter(queued_tracks::guild_id.eq(queue.guild_id))
+                    .order(queued_tracks::track_index.asc())
+                    .select(QueuedTrack::as_select())
+                    .load(connection)?;
+
+                Ok((queue, tracks))
+            })
+            .collect()
+    }
+}
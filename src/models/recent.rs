@@ -0,0 +1,58 @@
+use diesel::prelude::*;
+use serenity::all::UserId;
+
+use super::schema::recent_plays::{self, dsl};
+
+/// How many recent plays are kept for each user
+const MAX_RECENT_PLAYS: i64 = 25;
+
+/// A track the user requested, so it can be suggested to them again
+#[derive(Insertable, Queryable, Selectable)]
+#[diesel(table_name = recent_plays)]
+pub struct RecentPlay {
+    pub user_id: i64,
+    /// What can be played to request the track again, such as a link to it
+    pub query: String,
+    pub title: String,
+    /// Unix timestamp in milliseconds
+    pub played_at: i64,
+}
+
+impl RecentPlay {
+    /// Saves the play, forgetting the oldest plays of the user once there are too many
+    pub fn record(&self, connection: &mut SqliteConnection) -> QueryResult<()> {
+        connection.transaction(|connection| {
+            diesel::replace_into(dsl::recent_plays)
+                .values(self)
+                .execute(connection)?;
+
+            let oldest_kept = dsl::recent_plays
+                .filter(dsl::user_id.eq(self.user_id))
+                .order(dsl::played_at.desc())
+                .offset(MAX_RECENT_PLAYS - 1)
+                .select(dsl::played_at)
+                .first::<i64>(connection)
+                .optional()?;
+
+            if let Some(oldest_kept) = oldest_kept {
+                diesel::delete(
+                    dsl::recent_plays
+                        .filter(dsl::user_id.eq(self.user_id))
+                        .filter(dsl::played_at.lt(oldest_kept)),
+                )
+                .execute(connection)?;
+            }
+
+            Ok(())
+        })
+    }
+
+    /// The user's plays, most recent first
+    pub fn list(connection: &mut SqliteConnection, user: UserId) -> QueryResult<Vec<RecentPlay>> {
+        dsl::recent_plays
+            .filter(dsl::user_id.eq(user.get() as i64))
+            .order(dsl::played_at.desc())
+            .select(RecentPlay::as_select())
+            .load(connection)
+    }
+}
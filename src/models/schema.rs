@@ -1,2 +1,105 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    guild_settings (guild_id) {
+        guild_id -> BigInt,
+        dj_role -> Nullable<BigInt>,
+        always_connected -> Bool,
+        normalize -> Bool,
+        crossfade -> Integer,
+        playlist_role -> Nullable<BigInt>,
+        fair_queue -> Bool,
+        prefix -> Nullable<Text>,
+        announce_songs -> Bool,
+        default_volume -> Integer,
+        max_queue_length -> Nullable<Integer>,
+        max_track_duration -> Nullable<Integer>,
+        music_channel -> Nullable<BigInt>,
+    }
+}
+
+diesel::table! {
+    library (path) {
+        path -> Text,
+        title -> Text,
+        artist -> Nullable<Text>,
+        album -> Nullable<Text>,
+        duration -> Nullable<Integer>,
+        modified -> BigInt,
+    }
+}
+
+diesel::table! {
+    loudness (source) {
+        source -> Text,
+        integrated -> Double,
+    }
+}
+
+diesel::table! {
+    playlist_tracks (playlist_id, track_index) {
+        playlist_id -> Integer,
+        track_index -> Integer,
+        source -> Text,
+        title -> Text,
+        author -> Nullable<Text>,
+        duration -> Nullable<Integer>,
+        url -> Nullable<Text>,
+        thumbnail -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    playlists (id) {
+        id -> Integer,
+        owner_id -> BigInt,
+        guild -> Bool,
+        name -> Text,
+    }
+}
+
+diesel::table! {
+    queued_tracks (guild_id, track_index) {
+        guild_id -> BigInt,
+        track_index -> Integer,
+        source -> Text,
+        title -> Text,
+        author -> Nullable<Text>,
+        duration -> Nullable<Integer>,
+        url -> Nullable<Text>,
+        thumbnail -> Nullable<Text>,
+        requester -> Nullable<BigInt>,
+        enqueued_at -> BigInt,
+    }
+}
+
+diesel::table! {
+    recent_plays (user_id, query) {
+        user_id -> BigInt,
+        query -> Text,
+        title -> Text,
+        played_at -> BigInt,
+    }
+}
+
+diesel::table! {
+    saved_queues (guild_id) {
+        guild_id -> BigInt,
+        voice_channel -> BigInt,
+        text_channel -> BigInt,
+        loop_mode -> Text,
+        volume -> Integer,
+        position -> BigInt,
+    }
+}
+
+diesel::allow_tables_to_appear_in_same_query!(
+    guild_settings,
+    library,
+    loudness,
+    playlist_tracks,
+    playlists,
+    queued_tracks,
+    recent_plays,
+    saved_queues,
+);
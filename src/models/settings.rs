@@ -0,0 +1,391 @@
+use std::{fmt, str::FromStr, time::Duration};
+
+use diesel::prelude::*;
+use serenity::{
+    all::{ChannelId, GuildId, RoleId},
+    prelude::Mentionable,
+};
+
+use crate::helper::helper::{format_duration, parse_timestamp};
+
+use super::schema::guild_settings::{self, dsl};
+
+/// Prefix of the commands in guilds that didn't choose their own
+pub const DEFAULT_PREFIX: &str = "!";
+/// The loudest volume, in percent
+pub const MAX_VOLUME: i32 = 200;
+/// Longer prefixes are a pain to type and easily clash with normal messages
+const MAX_PREFIX_LENGTH: usize = 5;
+/// The most tracks a guild may allow in its queue
+const MAX_QUEUE_LENGTH: i32 = 10000;
+
+/// Settings that can be changed by each guild
+#[derive(Insertable, Queryable, Selectable, Clone)]
+#[diesel(table_name = guild_settings)]
+pub struct GuildSettings {
+    pub guild_id: i64,
+    /// Role that is allowed to use destructive music commands, everyone is allowed when unset
+    pub dj_role: Option<i64>,
+    /// Stay in the voice channel when idle or when everyone left, also known as 24/7 mode
+    pub always_connected: bool,
+    /// Play every track at about the same loudness
+    pub normalize: bool,
+    /// Seconds the end of a track overlaps with the start of the next, 0 plays them one after another
+    pub crossfade: i32,
+    /// Role that is allowed to edit the guild's playlists, only managers can when unset
+    pub playlist_role: Option<i64>,
+    /// Let requesters take turns instead of playing tracks in the order they were added
+    pub fair_queue: bool,
+    /// Prefix of the commands, [`DEFAULT_PREFIX`] when unset
+    pub prefix: Option<String>,
+    /// Show the player with what's playing in the music channel
+    pub announce_songs: bool,
+    /// Volume in percent the music starts at after joining a voice channel
+    pub default_volume: i32,
+    /// How many tracks may be queued at once, there is no limit when unset
+    pub max_queue_length: Option<i32>,
+    /// Seconds a track may last at most, tracks of any length can be played when unset
+    pub max_track_duration: Option<i32>,
+    /// Channel the player is shown in, the channel music was first requested in when unset
+    pub music_channel: Option<i64>,
+}
+
+impl GuildSettings {
+    pub fn new(guild: GuildId) -> Self {
+        GuildSettings {
+            guild_id: guild.get() as i64,
+            dj_role: None,
+            always_connected: false,
+            normalize: false,
+            crossfade: 0,
+            playlist_role: None,
+            fair_queue: false,
+            prefix: None,
+            announce_songs: true,
+            default_volume: 100,
+            max_queue_length: None,
+            max_track_duration: None,
+            music_channel: None,
+        }
+    }
+
+    /// Load the settings of the guild, falling back to the defaults if they were never saved
+    pub fn load(connection: &mut SqliteConnection, guild: GuildId) -> QueryResult<Self> {
+        let settings = dsl::guild_settings
+            .find(guild.get() as i64)
+            .select(GuildSettings::as_select())
+            .first(connection)
+            .optional()?;
+
+        Ok(settings.unwrap_or_else(|| GuildSettings::new(guild)))
+    }
+
+    pub fn save(&self, connection: &mut SqliteConnection) -> QueryResult<usize> {
+        diesel::replace_into(dsl::guild_settings)
+            .values(self)
+            .execute(connection)
+    }
+
+    pub fn guild(&self) -> GuildId {
+        GuildId::new(self.guild_id as u64)
+    }
+
+    pub fn dj_role(&self) -> Option<RoleId> {
+        self.dj_role.map(|role| RoleId::new(role as u64))
+    }
+
+    pub fn set_dj_role(&mut self, role: Option<RoleId>) {
+        self.dj_role = role.map(|role| role.get() as i64);
+    }
+
+    pub fn playlist_role(&self) -> Option<RoleId> {
+        self.playlist_role.map(|role| RoleId::new(role as u64))
+    }
+
+    pub fn set_playlist_role(&mut self, role: Option<RoleId>) {
+        self.playlist_role = role.map(|role| role.get() as i64);
+    }
+
+    pub fn crossfade(&self) -> Duration {
+        Duration::from_secs(self.crossfade as u64)
+    }
+
+    pub fn prefix(&self) -> &str {
+        self.prefix.as_deref().unwrap_or(DEFAULT_PREFIX)
+    }
+
+    /// The volume the music starts at, where 1.0 is the original volume
+    pub fn default_volume(&self) -> f32 {
+        self.default_volume as f32 / 100.0
+    }
+
+    pub fn max_queue_length(&self) -> Option<usize> {
+        self.max_queue_length.map(|length| length as usize)
+    }
+
+    pub fn max_track_duration(&self) -> Option<Duration> {
+        self.max_track_duration
+            .map(|seconds| Duration::from_secs(seconds as u64))
+    }
+
+    pub fn music_channel(&self) -> Option<ChannelId> {
+        self.music_channel
+            .map(|channel| ChannelId::new(channel as u64))
+    }
+
+    /// The value of the setting as it's shown to users
+    pub fn display(&self, setting: Setting) -> String {
+        match setting {
+            Setting::Prefix => format!("`{}`", self.prefix()),
+            Setting::AnnounceSongs => (if self.announce_songs { "on" } else { "off" }).to_string(),
+            Setting::DjRole => match self.dj_role() {
+                Some(role) => role.mention().to_string(),
+                None => "none".to_string(),
+            },
+            Setting::DefaultVolume => format!("{}%", self.default_volume),
+            Setting::MaxQueueLength => match self.max_queue_length {
+                Some(length) => format!("{} tracks", length),
+                None => "unlimited".to_string(),
+            },
+            Setting::MaxTrackDuration => match self.max_track_duration() {
+                Some(duration) => format_duration(duration),
+                None => "unlimited".to_string(),
+            },
+            Setting::MusicChannel => match self.music_channel() {
+                Some(channel) => channel.mention().to_string(),
+                None => "where music is requested".to_string(),
+            },
+        }
+    }
+
+    /// Changes the setting to the value a user gave, `reset` changes it back to the default.
+    /// Whether the DJ role and music channel exist in the guild is left to the caller.
+    pub fn set(&mut self, setting: Setting, value: &str) -> Result<(), String> {
+        let value = value.trim();
+        if value.eq_ignore_ascii_case("reset") {
+            self.reset(setting);
+            return Ok(());
+        }
+
+        match setting {
+            Setting::Prefix => {
+                if value.is_empty()
+                    || value.chars().count() > MAX_PREFIX_LENGTH
+                    || value.contains(char::is_whitespace)
+                {
+                    return Err(format!(
+                        "The prefix must be 1 to {} characters long without spaces",
+                        MAX_PREFIX_LENGTH
+                    ));
+                }
+
+                self.prefix = Some(value.to_string());
+            }
+            Setting::AnnounceSongs => {
+                self.announce_songs = match value.to_lowercase().as_str() {
+                    "on" | "true" | "yes" => true,
+                    "off" | "false" | "no" => false,
+                    _ => return Err("Use `on` or `off` to toggle the player".to_string()),
+                };
+            }
+            Setting::DjRole => {
+                let role = match value {
+                    "none" | "off" => None,
+                    role => Some(role.parse::<RoleId>().map_err(|_| {
+                        "Mention the role or use its id to set the DJ role".to_string()
+                    })?),
+                };
+
+                self.set_dj_role(role);
+            }
+            Setting::DefaultVolume => {
+                self.default_volume = value
+                    .trim_end_matches('%')
+                    .parse::<i32>()
+                    .ok()
+                    .filter(|volume| (0..=MAX_VOLUME).contains(volume))
+                    .ok_or_else(|| format!("The volume must be between 0 and {}", MAX_VOLUME))?;
+            }
+            Setting::MaxQueueLength => {
+                self.max_queue_length = match value {
+                    "none" | "off" | "unlimited" => None,
+                    length => Some(
+                        length
+                            .parse::<i32>()
+                            .ok()
+                            .filter(|length| (1..=MAX_QUEUE_LENGTH).contains(length))
+                            .ok_or_else(|| {
+                                format!(
+                                    "The queue length must be between 1 and {} tracks, or `none`",
+                                    MAX_QUEUE_LENGTH
+                                )
+                            })?,
+                    ),
+                };
+            }
+            Setting::MaxTrackDuration => {
+                self.max_track_duration = match value {
+                    "none" | "off" | "unlimited" => None,
+                    duration => Some(
+                        parse_timestamp(duration)
+                            .filter(|duration| !duration.is_zero())
+                            .and_then(|duration| i32::try_from(duration.as_secs()).ok())
+                            .ok_or_else(|| {
+                                "Use a duration such as `10:00` or `1:30:00`, or `none`".to_string()
+                            })?,
+                    ),
+                };
+            }
+            Setting::MusicChannel => {
+                self.music_channel = match value {
+                    "none" | "off" => None,
+                    channel => Some(
+                        channel
+                            .parse::<ChannelId>()
+                            .map_err(|_| {
+                                "Mention the channel or use its id to set the music channel"
+                                    .to_string()
+                            })?
+                            .get() as i64,
+                    ),
+                };
+            }
+        }
+
+        Ok(())
+    }
+
+    fn reset(&mut self, setting: Setting) {
+        let defaults = GuildSettings::new(self.guild());
+        match setting {
+            Setting::Prefix => self.prefix = defaults.prefix,
+            Setting::AnnounceSongs => self.announce_songs = defaults.announce_songs,
+            Setting::DjRole => self.dj_role = defaults.dj_role,
+            Setting::DefaultVolume => self.default_volume = defaults.default_volume,
+            Setting::MaxQueueLength => self.max_queue_length = defaults.max_queue_length,
+            Setting::MaxTrackDuration => self.max_track_duration = defaults.max_track_duration,
+            Setting::MusicChannel => self.music_channel = defaults.music_channel,
+        }
+    }
+}
+
+/// A setting that can be viewed and changed through the config command
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Setting {
+    Prefix,
+    AnnounceSongs,
+    DjRole,
+    DefaultVolume,
+    MaxQueueLength,
+    MaxTrackDuration,
+    MusicChannel,
+}
+
+impl Setting {
+    pub const ALL: [Setting; 7] = [
+        Setting::Prefix,
+        Setting::AnnounceSongs,
+        Setting::DjRole,
+        Setting::DefaultVolume,
+        Setting::MaxQueueLength,
+        Setting::MaxTrackDuration,
+        Setting::MusicChannel,
+    ];
+
+    /// The name the setting is changed by
+    pub fn name(&self) -> &'static str {
+        match self {
+            Setting::Prefix => "prefix",
+            Setting::AnnounceSongs => "announce-songs",
+            Setting::DjRole => "dj-role",
+            Setting::DefaultVolume => "default-volume",
+            Setting::MaxQueueLength => "max-queue-length",
+            Setting::MaxTrackDuration => "max-track-duration",
+            Setting::MusicChannel => "music-channel",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Setting::Prefix => "Prefix of the commands",
+            Setting::AnnounceSongs => "Show the player with what's playing, `on` or `off`",
+            Setting::DjRole => "Role that may use every music command, or `none`",
+            Setting::DefaultVolume => "Volume the music starts at after joining",
+            Setting::MaxQueueLength => "How many tracks may be queued at once, or `none`",
+            Setting::MaxTrackDuration => "How long a track may last, such as `10:00`, or `none`",
+            Setting::MusicChannel => "Channel the player is shown in, or `none`",
+        }
+    }
+}
+
+impl fmt::Display for Setting {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Setting {
+    type Err = ();
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let name = name.trim().to_lowercase().replace('_', "-");
+        Setting::ALL
+            .into_iter()
+            .find(|setting| setting.name() == name)
+            .ok_or(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> GuildSettings {
+        GuildSettings::new(GuildId::new(1))
+    }
+
+    #[test]
+    fn settings_are_found_by_name() {
+        assert_eq!("max-queue-length".parse(), Ok(Setting::MaxQueueLength));
+        assert_eq!("DJ_ROLE".parse(), Ok(Setting::DjRole));
+        assert!("volume".parse::<Setting>().is_err());
+    }
+
+    #[test]
+    fn values_are_validated() {
+        let mut settings = settings();
+
+        assert!(settings.set(Setting::Prefix, "too long").is_err());
+        assert!(settings.set(Setting::DefaultVolume, "201").is_err());
+        assert!(settings.set(Setting::MaxQueueLength, "0").is_err());
+        assert!(settings.set(Setting::MaxTrackDuration, "soon").is_err());
+        assert_eq!(settings.prefix(), DEFAULT_PREFIX);
+        assert_eq!(settings.default_volume, 100);
+
+        settings.set(Setting::Prefix, "?").unwrap();
+        settings.set(Setting::DefaultVolume, "50%").unwrap();
+        settings.set(Setting::MaxTrackDuration, "10:00").unwrap();
+        settings.set(Setting::MusicChannel, "<#42>").unwrap();
+
+        assert_eq!(settings.prefix(), "?");
+        assert_eq!(settings.default_volume(), 0.5);
+        assert_eq!(
+            settings.max_track_duration(),
+            Some(Duration::from_secs(600))
+        );
+        assert_eq!(settings.music_channel(), Some(ChannelId::new(42)));
+    }
+
+    #[test]
+    fn reset_restores_the_default() {
+        let mut settings = settings();
+        settings.set(Setting::AnnounceSongs, "off").unwrap();
+        settings.set(Setting::MaxQueueLength, "20").unwrap();
+
+        settings.set(Setting::AnnounceSongs, "reset").unwrap();
+        settings.set(Setting::MaxQueueLength, "reset").unwrap();
+
+        assert!(settings.announce_songs);
+        assert_eq!(settings.max_queue_length(), None);
+    }
+}